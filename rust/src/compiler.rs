@@ -1,15 +1,99 @@
+mod codegen;
 pub mod file;
 mod lexer;
 mod parser;
 mod sexp;
 
-pub fn compile<'a, T>(file: &'a T)
+use crate::vm::chunk::Chunk;
+
+// Each variant wraps the error type of the stage it's named after
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug)]
+pub enum Error {
+    LexError(lexer::LexError),
+    ParseError(parser::ParseError),
+    CompileError(codegen::CompileError),
+}
+
+impl Error {
+    pub fn offset(&self) -> usize {
+        match self {
+            Error::LexError(error) => error.offset,
+            Error::ParseError(error) => error.span.start,
+            Error::CompileError(error) => error.offset,
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::LexError(error) => write!(f, "SyntaxError: {}", error),
+            Error::ParseError(error) => write!(f, "SyntaxError: {}", error),
+            Error::CompileError(error) => write!(f, "CompileError: {}", error),
+        }
+    }
+}
+
+/// Compiles a file to bytecode, where `comments` is whether `#` starts a comment rather than being
+/// unexpected input
+pub fn compile<'a, T>(file: &'a T, comments: bool) -> Result<Chunk, Vec<Error>>
 where
     T: file::File<'a>,
 {
-    let tokens = lexer::tokenise(file);
-    println!("{:#?}", tokens);
+    let tokens = lexer::tokenise(file, comments).map_err(lex_errors)?;
+    let ast = parser::parse(file, &tokens).map_err(parse_errors)?;
+    codegen::generate(file, &ast).map_err(|error| vec![Error::CompileError(error)])
+}
+
+/// Renders every token in the file, including trivia, for debugging the lexer
+pub fn dump_tokens<'a, T>(file: &'a T, comments: bool) -> Result<String, Vec<Error>>
+where
+    T: file::File<'a>,
+{
+    let tokens = lexer::tokenise_lossless(file, comments).map_err(lex_errors)?;
+    Ok(format!("{:#?}", tokens))
+}
+
+fn lex_errors(errors: Vec<lexer::LexError>) -> Vec<Error> {
+    errors.into_iter().map(Error::LexError).collect()
+}
+
+fn parse_errors(errors: Vec<parser::ParseError>) -> Vec<Error> {
+    errors.into_iter().map(Error::ParseError).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::file::ScriptFile;
+    use super::*;
+
+    fn errors(source: &str) -> Vec<(usize, String)> {
+        let file = ScriptFile::new(source);
+        let errors = compile(&file, true).err().unwrap();
+        errors
+            .iter()
+            .map(|error| (error.offset(), error.to_string()))
+            .collect()
+    }
 
-    let ast = parser::parse(file, &tokens);
-    println!("{:#?}", ast);
+    // Messages leave the location out, since it's reported alongside them as `path:line:column`
+    #[test]
+    fn errors_give_their_offset_separately_from_the_message() {
+        assert_eq!(
+            errors("1 @"),
+            vec![(2, "SyntaxError: Unexpected input `@`".to_owned())]
+        );
+        assert_eq!(
+            errors("def x )"),
+            vec![(6, "SyntaxError: Expected value, got `)`".to_owned())]
+        );
+        assert_eq!(
+            errors("+ 1"),
+            vec![(
+                0,
+                "CompileError: Wrong number of arguments for `+` (given 1, expected 2)".to_owned()
+            )]
+        );
+    }
 }
@@ -0,0 +1,870 @@
+use super::file;
+use super::parser::{Ast, Node};
+use super::sexp::{Literal, Sexp};
+use crate::vm::chunk::Chunk;
+use crate::vm::value::{Function, Value};
+use crate::vm::Op;
+
+pub fn generate<'a, T: file::File<'a>>(file: &'a T, ast: &Ast<'_>) -> Result<Chunk, CompileError> {
+    // Imported files have to be loaded and compiled first, which isn't supported yet
+    if let Some(import) = ast.imports.first() {
+        return Err(CompileError::new(
+            import.offset,
+            format!(
+                "Imports are not supported yet, so `import {:?}` can't be compiled",
+                import.path
+            ),
+        ));
+    }
+    Generator::new(file.path()).generate(&ast.root)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompileError {
+    pub offset: usize,
+    pub message: String,
+}
+
+impl CompileError {
+    fn new(offset: usize, message: String) -> Self {
+        CompileError { offset, message }
+    }
+}
+
+impl std::fmt::Display for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+struct Generator {
+    chunk: Chunk,
+    // The parameters and local variables of each function being compiled, innermost last. Names
+    // outside of any function are globals.
+    scopes: Vec<Vec<String>>,
+}
+
+enum Variable {
+    Local(u32),
+    Global,
+}
+
+impl Generator {
+    fn new(file_path: &str) -> Self {
+        let mut chunk = Chunk::new();
+        chunk.literals.push(Value::String(file_path.to_owned()));
+        Generator {
+            chunk,
+            scopes: vec![],
+        }
+    }
+
+    fn generate(mut self, ast: &Sexp<'_, Node>) -> Result<Chunk, CompileError> {
+        self.generate_node(ast)?;
+        self.emit(Op::Return, &[], ast.offset());
+        Ok(self.chunk)
+    }
+
+    fn generate_node(&mut self, node: &Sexp<'_, Node>) -> Result<(), CompileError> {
+        match node.type_() {
+            Node::Block => self.generate_block(node),
+            Node::Call => self.generate_call(node),
+            Node::Constant => {
+                self.generate_constant(node);
+                Ok(())
+            }
+            Node::Constructor => self.generate_constructor(node),
+            Node::Def => self.generate_def(node),
+            Node::Defn => self.generate_defn(node),
+            Node::Deftype => self.generate_deftype(node),
+            Node::Fn => self.generate_function("anonymous", node),
+            Node::Guards => self.generate_guards(node),
+            Node::If | Node::Unless => self.generate_condition(node),
+            Node::Try => self.generate_try(node),
+            Node::Identifier => self.generate_identifier(node),
+            Node::Ref => self.generate_reference(&node.children()[0]),
+            Node::SymbolCall => self.generate_symbol_call(node),
+            Node::Documented => self.generate_node(&node.children()[1]),
+            Node::Interpolation => self.generate_interpolation(node),
+            Node::Array => self.generate_array(node),
+            Node::Hashmap => self.generate_hashmap(node),
+            Node::Defmodule | Node::ModuleScopedIdentifier | Node::ModuleScopedConstant => {
+                Err(unsupported_module(node))
+            }
+            Node::Integer | Node::Float | Node::String | Node::Symbol => {
+                self.generate_literal(node);
+                Ok(())
+            }
+            Node::True | Node::False | Node::Nil => {
+                let value = match node.type_() {
+                    Node::True => Value::Boolean(true),
+                    Node::False => Value::Boolean(false),
+                    _ => Value::Nil,
+                };
+                self.load(value, node.offset());
+                Ok(())
+            }
+            type_ => Err(CompileError::new(
+                node.offset(),
+                format!("Compiling {:?} is not implemented", type_),
+            )),
+        }
+    }
+
+    fn generate_block(&mut self, node: &Sexp<'_, Node>) -> Result<(), CompileError> {
+        let children = node.children();
+        if children.is_empty() {
+            self.load(Value::Nil, node.offset());
+            return Ok(());
+        }
+
+        for (index, child) in children.iter().enumerate() {
+            self.generate_node(child)?;
+            // Only the value of the last expression in a block is kept
+            if index < children.len() - 1 {
+                self.emit(Op::Pop, &[], child.offset());
+            }
+        }
+        Ok(())
+    }
+
+    // Operators and `raise` compile to their own instructions, while anything else is looked up
+    // and called. Module scoped names (`Foo.bar`) have no text of their own, so can never be
+    // operators.
+    fn generate_call(&mut self, node: &Sexp<'_, Node>) -> Result<(), CompileError> {
+        let (ident, args) = (&node.children()[0], node.children()[1].children());
+        let name = ident.text_content().unwrap_or_default();
+        let (op, arity) = match name {
+            "+" => (Op::Add, 2),
+            "-" => (Op::Subtract, 2),
+            "*" => (Op::Multiply, 2),
+            "/" => (Op::Divide, 2),
+            "%" => (Op::Modulo, 2),
+            "==" => (Op::Equal, 2),
+            "<" => (Op::Less, 2),
+            "<=" => (Op::LessEqual, 2),
+            ">" => (Op::Greater, 2),
+            ">=" => (Op::GreaterEqual, 2),
+            "raise" => (Op::Raise, 1),
+            _ => {
+                self.generate_reference(ident)?;
+                for arg in args {
+                    self.generate_node(arg)?;
+                }
+                self.emit(Op::Call, &[args.len() as u32], node.offset());
+                return Ok(());
+            }
+        };
+
+        if args.len() != arity {
+            return Err(CompileError::new(
+                node.offset(),
+                format!(
+                    "Wrong number of arguments for `{}` (given {}, expected {})",
+                    name,
+                    args.len(),
+                    arity
+                ),
+            ));
+        }
+
+        for arg in args {
+            self.generate_node(arg)?;
+        }
+        self.emit(op, &[], node.offset());
+        Ok(())
+    }
+
+    fn generate_deftype(&mut self, node: &Sexp<'_, Node>) -> Result<(), CompileError> {
+        let (name, supertype, fields) = (
+            &node.children()[0],
+            &node.children()[1],
+            &node.children()[2],
+        );
+        match supertype.children().first() {
+            Some(supertype) => self.generate_node(supertype)?,
+            None => self.load(Value::Nil, node.offset()),
+        }
+        self.generate_node(fields)?;
+        let name = self.add_literal(Value::String(name.text_content().unwrap().to_owned()));
+        self.emit(Op::DefineType, &[name], node.offset());
+        Ok(())
+    }
+
+    fn generate_constructor(&mut self, node: &Sexp<'_, Node>) -> Result<(), CompileError> {
+        let (constant, args) = (&node.children()[0], node.children()[1].children());
+        self.generate_node(constant)?;
+        for arg in args {
+            self.generate_node(arg)?;
+        }
+        self.emit(Op::Construct, &[args.len() as u32], node.offset());
+        Ok(())
+    }
+
+    fn generate_constant(&mut self, node: &Sexp<'_, Node>) {
+        let name = self.add_literal(Value::String(node.text_content().unwrap().to_owned()));
+        self.emit(Op::GetConstant, &[name], node.offset());
+    }
+
+    fn generate_def(&mut self, node: &Sexp<'_, Node>) -> Result<(), CompileError> {
+        let (ident, value) = (&node.children()[0], &node.children()[1]);
+        self.generate_node(value)?;
+        self.define(ident);
+        Ok(())
+    }
+
+    fn generate_defn(&mut self, node: &Sexp<'_, Node>) -> Result<(), CompileError> {
+        let ident = &node.children()[0];
+        self.generate_function(ident.text_content().unwrap(), node)?;
+        self.define(ident);
+        Ok(())
+    }
+
+    // Function bodies are compiled in place, behind a jump which skips over them, and the
+    // function value records where its body starts
+    fn generate_function(&mut self, name: &str, node: &Sexp<'_, Node>) -> Result<(), CompileError> {
+        let (params, body) = match node.children() {
+            [_, params, body] | [params, body] => (params, body),
+            _ => unreachable!("malformed function node {:?}", node),
+        };
+        let params: Vec<_> = params
+            .children()
+            .iter()
+            .map(|param| param.text_content().unwrap().to_owned())
+            .collect();
+
+        let jump = self.emit_jump(Op::Jump, node.offset());
+        let address = self.chunk.bytecode.len() as u32;
+        let arity = params.len() as u32;
+        self.scopes.push(params);
+        self.generate_node(body)?;
+        self.scopes.pop();
+        self.emit(Op::Return, &[], body.offset());
+        self.patch_jump(jump);
+
+        let function = Function {
+            name: name.to_owned(),
+            arity,
+            address,
+        };
+        self.load(Value::Function(function), node.offset());
+        Ok(())
+    }
+
+    // The body of the first guard whose condition is `true` is run, `else` always holds, and it's
+    // an error for no guard to hold. As in the Ruby implementation, a condition has to be exactly
+    // `true` to hold, rather than just truthy.
+    fn generate_guards(&mut self, node: &Sexp<'_, Node>) -> Result<(), CompileError> {
+        let guards = node.children();
+        if !guards
+            .iter()
+            .any(|guard| matches!(guard.type_(), Node::When))
+        {
+            return Err(CompileError::new(
+                node.offset(),
+                "Guards must include at least one `when`".to_owned(),
+            ));
+        }
+
+        let mut jumps_to_end = vec![];
+        for guard in guards {
+            match guard.children() {
+                [condition, body] => {
+                    self.generate_node(condition)?;
+                    self.load(Value::Boolean(true), guard.offset());
+                    self.emit(Op::Equal, &[], guard.offset());
+                    let next_guard = self.emit_jump(Op::JumpIfFalse, guard.offset());
+                    self.generate_node(body)?;
+                    jumps_to_end.push(self.emit_jump(Op::Jump, guard.offset()));
+                    self.patch_jump(next_guard);
+                }
+                [body] => {
+                    self.generate_node(body)?;
+                    jumps_to_end.push(self.emit_jump(Op::Jump, guard.offset()));
+                }
+                _ => unreachable!("malformed guard {:?}", guard),
+            }
+        }
+        self.emit(Op::NoMatchingGuard, &[], node.offset());
+
+        for jump in jumps_to_end {
+            self.patch_jump(jump);
+        }
+        Ok(())
+    }
+
+    // `unless` is an `if` with its branches swapped. A missing else branch gives nil.
+    fn generate_condition(&mut self, node: &Sexp<'_, Node>) -> Result<(), CompileError> {
+        let (condition, body, else_body) = match node.children() {
+            [condition, body] => (condition, body, None),
+            [condition, body, else_body] => (condition, body, Some(else_body)),
+            _ => unreachable!("malformed condition {:?}", node),
+        };
+        let (when_true, when_false) = match node.type_() {
+            Node::Unless => (else_body, Some(body)),
+            _ => (Some(body), else_body),
+        };
+
+        self.generate_node(condition)?;
+        let jump_to_false = self.emit_jump(Op::JumpIfFalse, node.offset());
+        self.generate_branch(when_true, node.offset())?;
+        let jump_to_end = self.emit_jump(Op::Jump, node.offset());
+        self.patch_jump(jump_to_false);
+        self.generate_branch(when_false, node.offset())?;
+        self.patch_jump(jump_to_end);
+        Ok(())
+    }
+
+    // While the body runs, an error raised inside it unwinds to the rescue clauses, which are tried
+    // in order with the error on the stack. The first whose type the error is an instance of
+    // calls its handler with the error, and if none match the error is raised again.
+    fn generate_try(&mut self, node: &Sexp<'_, Node>) -> Result<(), CompileError> {
+        let (body, rescues) = node.children().split_first().unwrap();
+        let handler = self.emit_jump(Op::PushHandler, node.offset());
+        self.generate_node(body)?;
+        self.emit(Op::PopHandler, &[], node.offset());
+        let mut jumps_to_end = vec![self.emit_jump(Op::Jump, node.offset())];
+
+        self.patch_jump(handler);
+        for rescue in rescues {
+            let (type_, handler) = (&rescue.children()[0], &rescue.children()[1]);
+            self.generate_node(type_)?;
+            let jump_to_next = self.emit_jump(Op::JumpIfNotInstance, rescue.offset());
+            self.generate_reference(handler)?;
+            self.emit(Op::Swap, &[], rescue.offset());
+            self.emit(Op::Call, &[1], rescue.offset());
+            jumps_to_end.push(self.emit_jump(Op::Jump, rescue.offset()));
+            self.patch_jump(jump_to_next);
+        }
+        self.emit(Op::Raise, &[], node.offset());
+
+        for jump in jumps_to_end {
+            self.patch_jump(jump);
+        }
+        Ok(())
+    }
+
+    fn generate_branch(
+        &mut self,
+        branch: Option<&Sexp<'_, Node>>,
+        offset: usize,
+    ) -> Result<(), CompileError> {
+        match branch {
+            Some(branch) => self.generate_node(branch),
+            None => {
+                self.load(Value::Nil, offset);
+                Ok(())
+            }
+        }
+    }
+
+    // As in the Ruby implementation, naming a function on its own calls it with no arguments,
+    // while other values are left as they are. `&` refers to the function instead.
+    fn generate_identifier(&mut self, ident: &Sexp<'_, Node>) -> Result<(), CompileError> {
+        self.generate_variable(ident)?;
+        self.emit(Op::Call, &[0], ident.offset());
+        Ok(())
+    }
+
+    // Loads what a name refers to without calling it
+    fn generate_reference(&mut self, target: &Sexp<'_, Node>) -> Result<(), CompileError> {
+        match target.type_() {
+            Node::Identifier => self.generate_variable(target),
+            _ => self.generate_node(target),
+        }
+    }
+
+    // `:name user` looks up the `name` field of an instance, or the `:name` key of a hashmap
+    fn generate_symbol_call(&mut self, node: &Sexp<'_, Node>) -> Result<(), CompileError> {
+        let (symbol, callee) = (&node.children()[0], &node.children()[1]);
+        self.generate_node(callee)?;
+        self.generate_literal(symbol);
+        self.emit(Op::GetField, &[], node.offset());
+        Ok(())
+    }
+
+    fn generate_variable(&mut self, ident: &Sexp<'_, Node>) -> Result<(), CompileError> {
+        let name = ident.text_content().unwrap();
+        match self.resolve(name, ident.offset())? {
+            Variable::Local(slot) => self.emit(Op::GetLocal, &[slot], ident.offset()),
+            Variable::Global => {
+                let index = self.add_literal(Value::String(name.to_owned()));
+                self.emit(Op::GetGlobal, &[index], ident.offset());
+            }
+        }
+        Ok(())
+    }
+
+    // Binds the value on top of the stack to a name, leaving it there as the result
+    fn define(&mut self, ident: &Sexp<'_, Node>) {
+        let name = ident.text_content().unwrap();
+        match self.scopes.last_mut() {
+            Some(scope) => {
+                let slot = match scope.iter().position(|local| local == name) {
+                    Some(slot) => slot,
+                    None => {
+                        scope.push(name.to_owned());
+                        scope.len() - 1
+                    }
+                };
+                self.emit(Op::SetLocal, &[slot as u32], ident.offset());
+            }
+            None => {
+                let index = self.add_literal(Value::String(name.to_owned()));
+                self.emit(Op::SetGlobal, &[index], ident.offset());
+            }
+        }
+    }
+
+    fn resolve(&self, name: &str, offset: usize) -> Result<Variable, CompileError> {
+        let mut scopes = self.scopes.iter().rev();
+        if let Some(slot) = scopes
+            .next()
+            .and_then(|scope| scope.iter().position(|local| local == name))
+        {
+            return Ok(Variable::Local(slot as u32));
+        }
+
+        if scopes.any(|scope| scope.iter().any(|local| local == name)) {
+            return Err(CompileError::new(
+                offset,
+                format!(
+                    "Using `{}` from an enclosing function is not implemented",
+                    name
+                ),
+            ));
+        }
+        Ok(Variable::Global)
+    }
+
+    fn generate_interpolation(&mut self, node: &Sexp<'_, Node>) -> Result<(), CompileError> {
+        let mut count = 0;
+        for child in node.children() {
+            match (child.type_(), child.literal()) {
+                (Node::String, Some(Literal::String(segment))) if segment.is_empty() => continue,
+                (Node::String, _) => self.generate_literal(child),
+                _ => {
+                    self.generate_node(child)?;
+                    self.emit(Op::ToString, &[], child.offset());
+                }
+            }
+            count += 1;
+        }
+        self.emit(Op::Concat, &[count], node.offset());
+        Ok(())
+    }
+
+    fn generate_array(&mut self, node: &Sexp<'_, Node>) -> Result<(), CompileError> {
+        for element in node.children() {
+            self.generate_node(element)?;
+        }
+        self.emit(
+            Op::MakeArray,
+            &[node.children().len() as u32],
+            node.offset(),
+        );
+        Ok(())
+    }
+
+    fn generate_hashmap(&mut self, node: &Sexp<'_, Node>) -> Result<(), CompileError> {
+        let elements = node.children();
+        for element in elements {
+            self.generate_node(element)?;
+        }
+        self.emit(Op::MakeHashmap, &[elements.len() as u32], node.offset());
+        Ok(())
+    }
+
+    fn generate_literal(&mut self, node: &Sexp<'_, Node>) {
+        let value = match node.literal().unwrap() {
+            Literal::Integer(value) => Value::Integer(*value),
+            Literal::Float(value) => Value::Float(*value),
+            Literal::String(value) => Value::String(value.clone()),
+            Literal::Symbol(value) => Value::Symbol(value.clone()),
+        };
+        self.load(value, node.offset());
+    }
+
+    fn load(&mut self, value: Value, offset: usize) {
+        let index = self.add_literal(value);
+        self.emit(Op::LoadLiteral, &[index], offset);
+    }
+
+    fn add_literal(&mut self, value: Value) -> u32 {
+        self.chunk.literals.push(value);
+        (self.chunk.literals.len() - 1) as u32
+    }
+
+    // Emits a jump to an address which isn't known yet, returning where to patch it in later
+    fn emit_jump(&mut self, op: Op, offset: usize) -> usize {
+        self.emit(op, &[u32::MAX], offset);
+        self.chunk.bytecode.len() - 8
+    }
+
+    // Points a jump emitted by `emit_jump` at the next instruction to be emitted
+    fn patch_jump(&mut self, position: usize) {
+        let address = self.chunk.bytecode.len() as u32;
+        self.chunk.bytecode[position..position + 4].copy_from_slice(&address.to_be_bytes());
+    }
+
+    // Instructions are laid out as [instruction, ...args, offset]
+    fn emit(&mut self, op: Op, args: &[u32], offset: usize) {
+        self.chunk.bytecode.push(op as u8);
+        for arg in args {
+            self.chunk.bytecode.extend(arg.to_be_bytes());
+        }
+        self.chunk.bytecode.extend((offset as u32).to_be_bytes());
+    }
+}
+
+// Modules can be parsed, but compiling them needs their names kept apart from each other, which
+// the VM's single namespace for globals can't do yet
+fn unsupported_module(node: &Sexp<'_, Node>) -> CompileError {
+    let text = |index: usize| node.children()[index].text_content().unwrap();
+    let name = match node.type_() {
+        Node::Defmodule => format!("defmodule {}", text(0)),
+        Node::ModuleScopedIdentifier => format!("{}.{}", text(0), text(1)),
+        _ => format!("{}::{}", text(0), text(1)),
+    };
+    CompileError::new(
+        node.offset(),
+        format!(
+            "Modules are not supported yet, so `{}` can't be compiled",
+            name
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::{self, file::ScriptFile, Error};
+    use crate::vm::value::Value;
+    use crate::vm::VM;
+
+    fn run(source: &str) -> Option<Value> {
+        let file = ScriptFile::new(source);
+        let chunk = compiler::compile(&file, true).unwrap();
+        VM::new().interprete(chunk).unwrap()
+    }
+
+    fn run_error(source: &str) -> String {
+        let file = ScriptFile::new(source);
+        let chunk = compiler::compile(&file, true).unwrap();
+        match VM::new().interprete(chunk) {
+            Err(error) => error.to_string(),
+            Ok(value) => panic!("expected {:?} to fail, got {:?}", source, value),
+        }
+    }
+
+    // The offset and message of the error from compiling the source
+    fn compile_error(source: &str) -> (usize, String) {
+        let file = ScriptFile::new(source);
+        match compiler::compile(&file, true) {
+            Err(errors) => match &errors[..] {
+                [Error::CompileError(error)] => (error.offset, error.to_string()),
+                errors => panic!("expected a single compile error, got {:?}", errors),
+            },
+            Ok(_) => panic!("expected {:?} not to compile", source),
+        }
+    }
+
+    #[test]
+    fn literals() {
+        assert_eq!(run("1"), Some(Value::Integer(1)));
+        assert_eq!(run("-1.5"), Some(Value::Float(-1.5)));
+        assert_eq!(run(r#""hi""#), Some(Value::String("hi".to_owned())));
+        assert_eq!(run("true"), Some(Value::Boolean(true)));
+        assert_eq!(run("nil"), Some(Value::Nil));
+        assert_eq!(run(":foo"), Some(Value::Symbol("foo".to_owned())));
+    }
+
+    #[test]
+    fn collections() {
+        assert_eq!(
+            run("[1 (+ 1 1) [false]]"),
+            Some(Value::Array(vec![
+                Value::Integer(1),
+                Value::Integer(2),
+                Value::Array(vec![Value::Boolean(false)])
+            ]))
+        );
+        assert_eq!(
+            run("{:a 1, :b 2, :a 3}").unwrap().to_string(),
+            r#"{:a 3 :b 2}"#
+        );
+        assert_eq!(run(r#"== ["a" :b] ["a" :b]"#), Some(Value::Boolean(true)));
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(run("+ 1 2"), Some(Value::Integer(3)));
+        assert_eq!(run("- 1 0.5"), Some(Value::Float(0.5)));
+        assert_eq!(run("* 2.5 4"), Some(Value::Float(10.0)));
+        assert_eq!(run("/ 1.0 4"), Some(Value::Float(0.25)));
+    }
+
+    #[test]
+    fn string_interpolation() {
+        let string = |value: &str| Some(Value::String(value.to_owned()));
+        assert_eq!(run(r#""1 + 2 = #{+ 1 2}""#), string("1 + 2 = 3"));
+        assert_eq!(run(r##""#{1.5}#{"a"}""##), string("1.5a"));
+        assert_eq!(run(r##""x#{"#{* 2 3}"}y""##), string("x6y"));
+    }
+
+    #[test]
+    fn functions() {
+        assert_eq!(run("defn inc n -> + n 1\ninc 41"), Some(Value::Integer(42)));
+        let source = "defn f a b do\n  def c 10\n  - c b\nend\nf 1 2";
+        assert_eq!(run(source), Some(Value::Integer(8)));
+        assert_eq!(
+            run("def x 2\ndefn double -> * x 2\ndouble"),
+            Some(Value::Integer(4))
+        );
+        assert_eq!(run("defn nothing do end\nnothing"), Some(Value::Nil));
+    }
+
+    #[test]
+    fn conditions() {
+        assert_eq!(run("if < 1 2 -> 3"), Some(Value::Integer(3)));
+        assert_eq!(run("if > 1 2 -> 3"), Some(Value::Nil));
+        let source = "unless == 1 2 do\n  \"yes\"\nelse\n  \"no\"\nend";
+        assert_eq!(run(source), Some(Value::String("yes".to_owned())));
+    }
+
+    #[test]
+    fn guards() {
+        let sign = "defn sign n\n  when < n 0 -> -1\n  when == n 0 -> 0\n  else -> 1\nend\n";
+        assert_eq!(run(&format!("{}sign -5", sign)), Some(Value::Integer(-1)));
+        assert_eq!(run(&format!("{}sign 0", sign)), Some(Value::Integer(0)));
+        assert_eq!(run(&format!("{}sign 3", sign)), Some(Value::Integer(1)));
+        assert_eq!(
+            run_error("defn negative n\n  when < n 0 -> true_value\nend\nnegative 1"),
+            "NoMatchingGuardError: No guard matched"
+        );
+        assert_eq!(
+            run_error("defn f n\n  when n -> 1\nend\nf 2"),
+            "NoMatchingGuardError: No guard matched"
+        );
+        assert_eq!(
+            compile_error("defn f n\n  else -> 1\nend"),
+            (11, "Guards must include at least one `when`".to_owned())
+        );
+    }
+
+    #[test]
+    fn types() {
+        let source = "deftype User [:name :email]\n";
+        assert_eq!(
+            run(&format!("{}User \"Test\" \"a@b\"", source))
+                .unwrap()
+                .to_string(),
+            "User {:name Test, :email a@b}"
+        );
+        assert_eq!(
+            run(&format!("{}User {{:email \"a@b\"}}", source))
+                .unwrap()
+                .to_string(),
+            "User {:name nil, :email a@b}"
+        );
+        let source = "deftype MyError Error [:code]\nMyError \"Oops\" 1";
+        assert_eq!(
+            run(source).unwrap().to_string(),
+            "MyError {:message Oops, :code 1}"
+        );
+        assert_eq!(
+            run_error("deftype Thing\ndeftype Thing"),
+            "NameError: type Thing is already defined"
+        );
+        assert_eq!(
+            run_error("deftype Integer"),
+            "NameError: type Integer is already defined"
+        );
+        assert_eq!(
+            run_error("Integer 1"),
+            "TypeError: Type `Integer` is not constructable"
+        );
+        assert_eq!(
+            run_error("deftype User [\"name\"]"),
+            "TypeError: Expected type Symbol for field, got \"name\""
+        );
+        assert_eq!(
+            run_error("Missing 1"),
+            "NameError: No constant with name Missing found"
+        );
+    }
+
+    #[test]
+    fn unsupported_modules() {
+        let unsupported = |name: &str| {
+            format!(
+                "Modules are not supported yet, so `{}` can't be compiled",
+                name
+            )
+        };
+        assert_eq!(
+            compile_error("defmodule Foo do\n  def x 1\nend"),
+            (0, unsupported("defmodule Foo"))
+        );
+        assert_eq!(compile_error("Foo.bar 1"), (0, unsupported("Foo.bar")));
+        assert_eq!(compile_error("Foo::Bar 1"), (0, unsupported("Foo::Bar")));
+        assert_eq!(
+            compile_error("deftype NotFound Http::Error"),
+            (17, unsupported("Http::Error"))
+        );
+    }
+
+    #[test]
+    fn unsupported_imports() {
+        assert_eq!(
+            compile_error("import \"test\"\nfoo"),
+            (
+                7,
+                "Imports are not supported yet, so `import \"test\"` can't be compiled".to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn field_lookups() {
+        assert_eq!(
+            run("def a {:foo \"bar\" 1 2}\n(:foo a)"),
+            Some(Value::String("bar".to_owned()))
+        );
+        assert_eq!(run(":baz {:foo 1}"), Some(Value::Nil));
+        let source = "deftype User [:name :email]\ndef u (User \"Test\" \"a@b\")\n";
+        assert_eq!(
+            run(&format!("{}:email u", source)),
+            Some(Value::String("a@b".to_owned()))
+        );
+        assert_eq!(
+            run(&format!("{}defn user -> u\n:name user", source)),
+            Some(Value::String("Test".to_owned()))
+        );
+        assert_eq!(
+            run_error(&format!("{}:age u", source)),
+            "NameError: User has no field :age"
+        );
+        assert_eq!(
+            run_error(":foo (+ 1 1)"),
+            "TypeError: Cannot look up :foo in 2"
+        );
+    }
+
+    #[test]
+    fn references() {
+        let source = "defn one -> 1\ndef f &one\n";
+        assert_eq!(run(&format!("{}one", source)), Some(Value::Integer(1)));
+        assert_eq!(run(&format!("{}+ one f", source)), Some(Value::Integer(2)));
+        assert_eq!(
+            run(&format!("{}&f", source)).unwrap().to_string(),
+            "<fn: one (0)>"
+        );
+        assert_eq!(
+            run("defn inc n -> + n 1\ndefn apply f x -> f x\napply &inc 1"),
+            Some(Value::Integer(2))
+        );
+        assert_eq!(run("deftype Thing\n&Thing").unwrap().to_string(), "Thing");
+        assert_eq!(
+            run_error("defn inc n -> + n 1\ndef f inc"),
+            "ArgumentError: Invalid number of arguments for <fn: inc (1)>, expected 1, got 0"
+        );
+    }
+
+    #[test]
+    fn raising_and_rescuing() {
+        let source = "deftype AssertionError Error\n\
+                      defn assert assertion do\n  \
+                        unless assertion -> raise (AssertionError \"Assertion failed\")\n\
+                      end\n\
+                      defn test test_fn do\n  \
+                        try do\n    \
+                          test_fn\n    \
+                          \".\"\n  \
+                        rescue AssertionError (fn e -> \"F\")\n  \
+                        rescue Error (fn e -> \"E: #{:message e}\")\n  \
+                        end\n\
+                      end\n";
+        let string = |value: &str| Some(Value::String(value.to_owned()));
+        assert_eq!(
+            run(&format!("{}test (fn -> assert true)", source)),
+            string(".")
+        );
+        assert_eq!(
+            run(&format!("{}test (fn -> assert false)", source)),
+            string("F")
+        );
+        assert_eq!(
+            run(&format!("{}test (fn -> missing)", source)),
+            string("E: No identifier with name missing found")
+        );
+        // The stack is unwound to where the try block started
+        assert_eq!(
+            run(&format!(
+                "{}\"a#{{test (fn -> + 1 (assert false))}}b\"",
+                source
+            )),
+            string("aFb")
+        );
+        assert_eq!(
+            run_error(&format!("{}assert false", source)),
+            "AssertionError: Assertion failed"
+        );
+    }
+
+    #[test]
+    fn unrescued_errors_are_raised_again() {
+        let source = "deftype A Error\ndeftype B Error\n\
+                      try do\n  raise (B \"oops\")\nrescue A (fn e -> 1)\nend";
+        assert_eq!(run_error(source), "B: oops");
+        assert_eq!(
+            run("try do\n  try do\n    raise (Error \"x\")\n  rescue NameError &f\n  end\nrescue Error (fn e -> 2)\nend"),
+            Some(Value::Integer(2))
+        );
+        assert_eq!(
+            run_error("raise 1"),
+            "TypeError: Expected type Error, got 1"
+        );
+    }
+
+    #[test]
+    fn runtime_errors() {
+        assert_eq!(
+            run_error("defn f a -> a\nf 1 2"),
+            "ArgumentError: Invalid number of arguments for <fn: f (1)>, expected 1, got 2"
+        );
+        assert_eq!(
+            run_error("foo 1"),
+            "NameError: No identifier with name foo found"
+        );
+    }
+
+    #[test]
+    fn runtime_errors_give_the_offset_they_were_raised_at() {
+        let file = ScriptFile::new("def x 1\n+ x \"a\"");
+        let chunk = compiler::compile(&file, true).unwrap();
+        let error = VM::new().interprete(chunk).unwrap_err();
+        assert_eq!(error.offset, 8);
+        assert_eq!(
+            error.to_string(),
+            "RuntimeError: invalid type String(\"a\") for Add"
+        );
+    }
+
+    #[test]
+    fn unsupported_calls() {
+        assert_eq!(
+            compile_error("+ 1"),
+            (
+                0,
+                "Wrong number of arguments for `+` (given 1, expected 2)".to_owned()
+            )
+        );
+        assert_eq!(
+            compile_error("defn outer a do\n  defn inner -> a\n  inner\nend"),
+            (
+                32,
+                "Using `a` from an enclosing function is not implemented".to_owned()
+            )
+        );
+    }
+}
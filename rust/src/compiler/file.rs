@@ -1,6 +1,7 @@
 pub struct RealFile {
     file_path: String,
     contents: String,
+    line_index: LineIndex,
 }
 
 impl RealFile {
@@ -8,19 +9,134 @@ impl RealFile {
         let contents = std::fs::read_to_string(&file_path)
             .map_err(|e| format!("{}", e))
             .unwrap();
+        let line_index = LineIndex::new(&contents);
         RealFile {
             file_path,
             contents,
+            line_index,
         }
     }
 }
 
 pub trait File<'a> {
+    fn path(&'a self) -> &'a str;
     fn contents(&'a self) -> &'a str;
+    fn line_index(&'a self) -> &'a LineIndex;
+
+    fn location(&'a self, offset: usize) -> Location {
+        self.line_index().location(self.contents(), offset)
+    }
 }
 
 impl<'a> File<'a> for RealFile {
+    fn path(&'a self) -> &'a str {
+        &self.file_path
+    }
+
+    fn contents(&'a self) -> &'a str {
+        &self.contents
+    }
+
+    fn line_index(&'a self) -> &'a LineIndex {
+        &self.line_index
+    }
+}
+
+/// A 1-based line and column in a source file. Columns count characters rather than bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// The byte offset at which each line of a file starts, for mapping offsets to locations
+pub struct LineIndex {
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(contents: &str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(contents.match_indices('\n').map(|(index, _)| index + 1))
+            .collect();
+        LineIndex { line_starts }
+    }
+
+    pub fn location(&self, contents: &str, offset: usize) -> Location {
+        let line = self.line_starts.partition_point(|&start| start <= offset);
+        let line_start = self.line_starts[line - 1];
+        let column = contents[line_start..]
+            .char_indices()
+            .take_while(|(index, _)| line_start + index < offset)
+            .count()
+            + 1;
+        Location { line, column }
+    }
+}
+
+#[cfg(test)]
+pub struct ScriptFile {
+    contents: String,
+    line_index: LineIndex,
+}
+
+#[cfg(test)]
+impl ScriptFile {
+    pub fn new(contents: &str) -> Self {
+        ScriptFile {
+            contents: contents.to_owned(),
+            line_index: LineIndex::new(contents),
+        }
+    }
+}
+
+#[cfg(test)]
+impl<'a> File<'a> for ScriptFile {
+    fn path(&'a self) -> &'a str {
+        "<script>"
+    }
+
     fn contents(&'a self) -> &'a str {
         &self.contents
     }
+
+    fn line_index(&'a self) -> &'a LineIndex {
+        &self.line_index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_offsets_to_lines_and_columns() {
+        let file = ScriptFile::new("foo 1\n\n  bar\n");
+        let location = |offset| file.location(offset);
+        assert_eq!(location(0), Location { line: 1, column: 1 });
+        assert_eq!(location(4), Location { line: 1, column: 5 });
+        assert_eq!(location(5), Location { line: 1, column: 6 });
+        assert_eq!(location(6), Location { line: 2, column: 1 });
+        assert_eq!(location(9), Location { line: 3, column: 3 });
+        assert_eq!(location(13), Location { line: 4, column: 1 });
+    }
+
+    #[test]
+    fn counts_columns_in_characters() {
+        let file = ScriptFile::new("\"héllo wörld\" @");
+        assert_eq!(
+            file.location(15),
+            Location {
+                line: 1,
+                column: 14
+            }
+        );
+        assert_eq!(file.location(15).to_string(), "1:14");
+    }
 }
@@ -1,57 +1,212 @@
 use super::file;
-use super::sexp::{s, t, Sexp};
-use std::slice::Iter;
+use super::sexp::{lit, t, Literal, Sexp};
+use std::collections::VecDeque;
+use std::num::IntErrorKind;
 
-use regex::Regex;
+pub type Token<'a> = Sexp<'a, TokenType>;
 
-pub fn tokenise<'a, F>(file: &'a F) -> Vec<Sexp<'a, TokenType>>
+#[cfg(test)]
+mod regex_lexer;
+
+/// Tokenises a file, where `comments` is whether `#` starts a comment
+pub fn tokenise<'a, F>(
+    file: &'a F,
+    comments: bool,
+) -> Result<Vec<Sexp<'a, TokenType>>, Vec<LexError>>
+where
+    F: file::File<'a>,
+{
+    lexer(file, comments).tokenise()
+}
+
+/// Tokenises a file without discarding anything, for tooling which needs to reproduce the
+/// source. Whitespace, commas and comments are attached as trivia to the token following them,
+/// and a final `Eof` token carries any trailing trivia, so concatenating the text of each
+/// token's trivia followed by the token itself rebuilds the file byte-for-byte.
+pub fn tokenise_lossless<'a, F>(
+    file: &'a F,
+    comments: bool,
+) -> Result<Vec<Sexp<'a, TokenType>>, Vec<LexError>>
 where
     F: file::File<'a>,
 {
-    Lexer::new(file.contents()).tokenise()
+    lexer(file, comments).tokenise_lossless()
+}
+
+fn lexer<'a, F>(file: &'a F, comments: bool) -> Lexer<'a>
+where
+    F: file::File<'a>,
+{
+    let lexer = Lexer::new(file.contents());
+    if comments {
+        lexer
+    } else {
+        lexer.without_comments()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LexError {
+    pub offset: usize,
+    pub character: char,
+    pub message: String,
+}
+
+impl LexError {
+    fn new(offset: usize, character: char, message: String) -> Self {
+        LexError {
+            offset,
+            character,
+            message,
+        }
+    }
+}
+
+impl std::fmt::Display for LexError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum TokenType {
     Integer,
+    Float,
     Space,
     Newline,
     Identifier,
+    String,
+    Symbol,
+    Constant,
+
+    // Pieces of an interpolated string: `"Hello #{`, `}, #{` and `}!"`, with the interpolated
+    // expressions lexed as normal tokens in between
+    InterpolationStart,
+    InterpolationMiddle,
+    InterpolationEnd,
+
+    // Punctuation
+    LeftParen,
+    RightParen,
+    LeftBracket,
+    RightBracket,
+    LeftBrace,
+    RightBrace,
+    Arrow,
+    Dot,
+    DoubleColon,
+    Ref,
+    Comma,
+    Comment,
+    DocComment,
+    // A `#!` interpreter line at the very start of an executable script
+    Shebang,
+    Eof,
+
+    // Keywords
+    Def,
+    Defn,
+    Deftype,
+    Defmodule,
+    Do,
+    Else,
+    End,
+    False,
+    Fn,
+    If,
+    Import,
+    Nil,
+    Rescue,
+    True,
+    Try,
+    Unless,
+    When,
 }
 
 impl TokenType {
-    fn iter() -> Iter<'static, TokenType> {
-        static TOKEN_TYPES: [TokenType; 4] = [
-            TokenType::Integer,
-            TokenType::Space,
-            TokenType::Newline,
-            TokenType::Identifier,
-        ];
-        TOKEN_TYPES.iter()
+    fn keyword(text: &str) -> Option<TokenType> {
+        match text {
+            "def" => Some(TokenType::Def),
+            "defn" => Some(TokenType::Defn),
+            "deftype" => Some(TokenType::Deftype),
+            "defmodule" => Some(TokenType::Defmodule),
+            "do" => Some(TokenType::Do),
+            "else" => Some(TokenType::Else),
+            "end" => Some(TokenType::End),
+            "false" => Some(TokenType::False),
+            "fn" => Some(TokenType::Fn),
+            "if" => Some(TokenType::If),
+            "import" => Some(TokenType::Import),
+            "nil" => Some(TokenType::Nil),
+            "rescue" => Some(TokenType::Rescue),
+            "true" => Some(TokenType::True),
+            "try" => Some(TokenType::Try),
+            "unless" => Some(TokenType::Unless),
+            "when" => Some(TokenType::When),
+            _ => None,
+        }
     }
 
-    fn skip(&self) -> bool {
+    /// How the token is referred to in syntax errors
+    pub fn description(&self) -> &'static str {
         match self {
-            TokenType::Space => true,
-            _ => false,
+            TokenType::Integer => "integer",
+            TokenType::Float => "float",
+            TokenType::Space => "space",
+            TokenType::Newline => "newline",
+            TokenType::Identifier => "identifier",
+            TokenType::String => "string",
+            TokenType::Symbol => "symbol",
+            TokenType::Constant => "constant",
+            TokenType::InterpolationStart => "interpolated string",
+            TokenType::InterpolationMiddle | TokenType::InterpolationEnd => "`}`",
+            TokenType::LeftParen => "`(`",
+            TokenType::RightParen => "`)`",
+            TokenType::LeftBracket => "`[`",
+            TokenType::RightBracket => "`]`",
+            TokenType::LeftBrace => "`{`",
+            TokenType::RightBrace => "`}`",
+            TokenType::Arrow => "`->`",
+            TokenType::Dot => "`.`",
+            TokenType::DoubleColon => "`::`",
+            TokenType::Ref => "`&`",
+            TokenType::Comma => "`,`",
+            TokenType::Comment => "comment",
+            TokenType::DocComment => "doc comment",
+            TokenType::Shebang => "shebang",
+            TokenType::Eof => "end of input",
+            TokenType::Def => "`def`",
+            TokenType::Defn => "`defn`",
+            TokenType::Deftype => "`deftype`",
+            TokenType::Defmodule => "`defmodule`",
+            TokenType::Do => "`do`",
+            TokenType::Else => "`else`",
+            TokenType::End => "`end`",
+            TokenType::False => "`false`",
+            TokenType::Fn => "`fn`",
+            TokenType::If => "`if`",
+            TokenType::Import => "`import`",
+            TokenType::Nil => "`nil`",
+            TokenType::Rescue => "`rescue`",
+            TokenType::True => "`true`",
+            TokenType::Try => "`try`",
+            TokenType::Unless => "`unless`",
+            TokenType::When => "`when`",
         }
     }
 
-    fn regex(&self) -> Regex {
-        match self {
-            TokenType::Integer => Regex::new(r"\A[0-9]+"),
-            TokenType::Space => Regex::new(r"\A[ \t]+"),
-            TokenType::Newline => Regex::new(r"\A\n|\A\r\n"),
-            TokenType::Identifier => {
-                Regex::new(r"\A[+\-\\/*%]|\A[><]=?|\A==|\A[a-z]+[a-zA-Z_0-9]*\??")
-            }
-        }
-        .unwrap()
+    fn is_documentable(&self) -> bool {
+        matches!(
+            self,
+            TokenType::Defn | TokenType::Deftype | TokenType::Defmodule
+        )
     }
 
-    fn matches<'a>(&self, text: &'a str, offset: usize) -> Option<Match<'a>> {
-        let match_ = self.regex().find(&text[offset..])?;
-        Some(Match::new(*self, match_.as_str(), offset))
+    fn skip(&self) -> bool {
+        matches!(
+            self,
+            TokenType::Space | TokenType::Comma | TokenType::Comment | TokenType::Shebang
+        )
     }
 }
 
@@ -75,42 +230,1738 @@ impl<'a> Match<'a> {
     }
 }
 
-struct Lexer<'a> {
+// A string interpolation that has been opened but not yet closed
+struct Interpolation {
+    // Offset of the string's opening quote
+    offset: usize,
+    // Braces opened inside the interpolated expression, so that a `}` closing a hashmap isn't
+    // mistaken for the end of the interpolation
+    open_braces: usize,
+}
+
+/// Lexes source text a token at a time. Tokens and errors are yielded in source order, and
+/// lexing carries on past errors, so the whole file can be checked in one pass.
+pub struct Lexer<'a> {
     source: &'a str,
     offset: usize,
+    at_line_start: bool,
+    interpolations: Vec<Interpolation>,
+    // Comment lines (and the newlines ending them) which will become doc comments if they turn
+    // out to immediately precede a definition
+    doc_comments: Vec<Sexp<'a, TokenType>>,
+    // Whether `#` starts a comment
+    comments: bool,
+    doc_newlines: Vec<Sexp<'a, TokenType>>,
+    // Tokens and errors which have been lexed but not yet returned
+    pending: VecDeque<Result<Token<'a>, LexError>>,
+    finished: bool,
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Result<Token<'a>, LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Lexing some text doesn't always produce a token straight away, e.g. for whitespace or
+        // comment lines that might be documenting a definition
+        while self.pending.is_empty() && !self.finished {
+            if self.at_end() {
+                self.finish();
+            } else {
+                self.lex();
+            }
+        }
+        self.pending.pop_front()
+    }
 }
 
 impl<'a> Lexer<'a> {
-    fn new(source: &'a str) -> Self {
-        Lexer { source, offset: 0 }
+    pub fn new(source: &'a str) -> Self {
+        Lexer::from_offset(source, 0)
+    }
+
+    /// Starts lexing part way through `source`, so that e.g. an edited line can be re-lexed
+    /// without re-scanning everything before it. `offset` should fall between two tokens outside
+    /// of any string; comments on the lines above it can't document a definition after it. An
+    /// offset inside a multibyte character is moved back to the start of the character.
+    pub fn from_offset(source: &'a str, mut offset: usize) -> Self {
+        while !source.is_char_boundary(offset) {
+            offset -= 1;
+        }
+
+        Lexer {
+            source,
+            offset,
+            at_line_start: offset == 0 || source[..offset].ends_with('\n'),
+            interpolations: vec![],
+            doc_comments: vec![],
+            doc_newlines: vec![],
+            pending: VecDeque::new(),
+            finished: false,
+            comments: true,
+        }
     }
 
-    fn tokenise(&mut self) -> Vec<Sexp<'a, TokenType>> {
-        let mut result = vec![];
+    /// Lexes `#` as unexpected input rather than the start of a comment. A `#!` line at the start
+    /// of the file is still skipped, as it's how the script is run rather than part of it.
+    pub fn without_comments(mut self) -> Self {
+        self.comments = false;
+        self
+    }
+
+    fn tokenise(self) -> Result<Vec<Sexp<'a, TokenType>>, Vec<LexError>> {
+        let mut tokens = vec![];
+        let mut errors = vec![];
+        for result in self {
+            match result {
+                Ok(token) => tokens.push(token),
+                Err(error) => errors.push(error),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(tokens)
+        } else {
+            Err(errors)
+        }
+    }
 
-        while !self.at_end() {
-            if let Some(match_) = self.sorted_matches().pop() {
-                self.offset += match_.len();
-                if !match_.token_type.skip() {
-                    result.push(t(match_.token_type, match_.text, match_.offset));
+    fn lex(&mut self) {
+        if let Some(match_) = self.next_match() {
+            self.offset += match_.len();
+            self.track_interpolation(&match_);
+            match match_.token_type {
+                TokenType::Comment => self.comment(&match_),
+                TokenType::String if match_.text.starts_with(MULTILINE_QUOTE) => {
+                    if let Some(token) = self.multiline_string_token(&match_) {
+                        self.push(token)
+                    }
                 }
-            } else {
-                panic!("Couldn't parse source!");
+                TokenType::String
+                | TokenType::InterpolationStart
+                | TokenType::InterpolationMiddle
+                | TokenType::InterpolationEnd => {
+                    if let Some(token) = self.string_token(&match_) {
+                        self.push(token)
+                    }
+                }
+                TokenType::Integer => {
+                    if let Some(token) = self.integer_token(&match_) {
+                        self.push(token)
+                    }
+                }
+                TokenType::Float => {
+                    if let Some(token) = self.float_token(&match_) {
+                        self.push(token)
+                    }
+                }
+                TokenType::Symbol => self.push(lit(
+                    TokenType::Symbol,
+                    match_.text,
+                    Literal::Symbol(match_.text[1..].to_owned()),
+                    match_.offset,
+                )),
+                token_type if !token_type.skip() => {
+                    self.push(t(token_type, match_.text, match_.offset))
+                }
+                _ => (),
             }
+        } else if self.source[self.offset..].starts_with(MULTILINE_QUOTE) {
+            // Nothing can close the string, so there's nothing left to lex
+            self.error(self.offset, "Unterminated string".to_owned());
+            self.offset = self.source.len();
+        } else if self.source[self.offset..].starts_with(['"', '}']) {
+            // Nothing after an unterminated string can close it, so resume on the next line
+            self.error(self.offset, "Unterminated string".to_owned());
+            self.interpolations.clear();
+            self.skip_while(|c| c != '\n');
+        } else {
+            let character = self.current_char();
+            self.error(self.offset, format!("Unexpected input `{}`", character));
+            self.offset += character.len_utf8();
+            self.skip_while(|c| !is_whitespace(c));
+        }
+    }
+
+    fn finish(&mut self) {
+        if let Some(interpolation) = self.interpolations.first() {
+            self.error(interpolation.offset, "Unterminated string".to_owned());
         }
 
-        result
+        self.flush_doc_comments();
+        self.finished = true;
     }
 
-    fn sorted_matches(&self) -> Vec<Match<'a>> {
-        let mut matches: Vec<Match<'a>> = TokenType::iter()
-            .filter_map(|t| t.matches(&self.source, self.offset))
-            .collect();
-        matches.sort_by(|a, b| a.len().cmp(&b.len()));
-        matches
+    fn tokenise_lossless(self) -> Result<Vec<Sexp<'a, TokenType>>, Vec<LexError>> {
+        let source = self.source;
+        let mut tokens = self.tokenise()?;
+        tokens.push(t(TokenType::Eof, "", source.len()));
+
+        let mut previous_end = 0;
+        for token in tokens.iter_mut() {
+            let span = token.span();
+            token.attach_trivia(trivia(source, previous_end, span.start));
+            previous_end = span.end;
+        }
+        Ok(tokens)
+    }
+
+    fn track_interpolation(&mut self, match_: &Match<'a>) {
+        match match_.token_type {
+            TokenType::InterpolationStart => self.interpolations.push(Interpolation {
+                offset: match_.offset,
+                open_braces: 0,
+            }),
+            TokenType::InterpolationEnd => {
+                self.interpolations.pop();
+            }
+            TokenType::LeftBrace => {
+                if let Some(interpolation) = self.interpolations.last_mut() {
+                    interpolation.open_braces += 1;
+                }
+            }
+            TokenType::RightBrace => {
+                if let Some(interpolation) = self.interpolations.last_mut() {
+                    interpolation.open_braces -= 1;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn error(&mut self, offset: usize, message: String) {
+        let character = self.source[offset..].chars().next().unwrap();
+        self.pending
+            .push_back(Err(LexError::new(offset, character, message)));
+    }
+
+    fn current_char(&self) -> char {
+        self.source[self.offset..].chars().next().unwrap()
+    }
+
+    fn skip_while<P: Fn(char) -> bool>(&mut self, predicate: P) {
+        self.offset = self.source[self.offset..]
+            .find(|c| !predicate(c))
+            .map_or(self.source.len(), |index| self.offset + index);
+    }
+
+    fn comment(&mut self, match_: &Match<'a>) {
+        // Trailing comments after an expression never document anything
+        if !self.at_line_start {
+            return;
+        }
+
+        let text = match_.text[1..]
+            .strip_prefix(' ')
+            .unwrap_or(&match_.text[1..]);
+        self.doc_comments.push(lit(
+            TokenType::DocComment,
+            match_.text,
+            Literal::String(text.trim_end().to_owned()),
+            match_.offset,
+        ));
+    }
+
+    fn push(&mut self, token: Sexp<'a, TokenType>) {
+        let token_type = token.type_();
+        if token_type == TokenType::Newline && self.doc_newlines.len() < self.doc_comments.len() {
+            self.doc_newlines.push(token);
+            return;
+        }
+
+        if token_type.is_documentable() {
+            self.pending.extend(self.doc_comments.drain(..).map(Ok));
+            self.doc_newlines.clear();
+        } else {
+            self.flush_doc_comments();
+        }
+
+        self.at_line_start = token_type == TokenType::Newline;
+        self.pending.push_back(Ok(token));
+    }
+
+    fn flush_doc_comments(&mut self) {
+        self.doc_comments.clear();
+        self.pending.extend(self.doc_newlines.drain(..).map(Ok));
+    }
+
+    // Plain strings and the pieces of interpolated strings all start with a single `"` or `}`,
+    // and end with either `"` or `#{`
+    fn string_token(&mut self, match_: &Match<'a>) -> Option<Sexp<'a, TokenType>> {
+        let closing_len = match match_.token_type {
+            TokenType::InterpolationStart | TokenType::InterpolationMiddle => 2,
+            _ => 1,
+        };
+        let inner = &match_.text[1..match_.len() - closing_len];
+        match unescape(inner) {
+            Ok(value) => Some(lit(
+                match_.token_type,
+                match_.text,
+                Literal::String(value),
+                match_.offset,
+            )),
+            Err((index, message)) => {
+                self.error(match_.offset + 1 + index, message);
+                None
+            }
+        }
+    }
+
+    fn multiline_string_token(&mut self, match_: &Match<'a>) -> Option<Sexp<'a, TokenType>> {
+        let quote_len = MULTILINE_QUOTE.len();
+        let inner = &match_.text[quote_len..match_.len() - quote_len];
+
+        let mut value = String::with_capacity(inner.len());
+        for (line_number, (index, line)) in dedent(inner).into_iter().enumerate() {
+            if line_number > 0 {
+                value.push('\n');
+            }
+            // Lines are unescaped one at a time so errors point into the original source rather
+            // than the dedented text
+            match unescape(line) {
+                Ok(line) => value.push_str(&line),
+                Err((error_index, message)) => {
+                    self.error(match_.offset + quote_len + index + error_index, message);
+                    return None;
+                }
+            }
+        }
+
+        Some(lit(
+            TokenType::String,
+            match_.text,
+            Literal::String(value),
+            match_.offset,
+        ))
+    }
+
+    fn integer_token(&mut self, match_: &Match<'a>) -> Option<Sexp<'a, TokenType>> {
+        let (sign, unsigned) = match match_.text.strip_prefix('-') {
+            Some(unsigned) => ("-", unsigned),
+            None => ("", match_.text),
+        };
+        let (radix, digits) = if let Some(digits) = unsigned.strip_prefix("0x") {
+            (16, digits)
+        } else if let Some(digits) = unsigned.strip_prefix("0b") {
+            (2, digits)
+        } else {
+            (10, unsigned)
+        };
+        if let Some(problem) = malformed_digits(digits, radix) {
+            self.error(
+                match_.offset,
+                format!("Integer literal `{}` {}", match_.text, problem),
+            );
+            return None;
+        }
+        let digits = format!("{}{}", sign, digits.replace('_', ""));
+
+        match i64::from_str_radix(&digits, radix) {
+            Ok(value) => Some(lit(
+                TokenType::Integer,
+                match_.text,
+                Literal::Integer(value),
+                match_.offset,
+            )),
+            Err(error) => {
+                let message = match error.kind() {
+                    IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => format!(
+                        "Integer literal `{}` is out of range for a 64-bit integer",
+                        match_.text
+                    ),
+                    _ => format!("Invalid integer literal `{}`", match_.text),
+                };
+                self.error(match_.offset, message);
+                None
+            }
+        }
+    }
+
+    fn float_token(&mut self, match_: &Match<'a>) -> Option<Sexp<'a, TokenType>> {
+        let unsigned = match_.text.trim_start_matches('-');
+        let bytes = unsigned.as_bytes();
+        let len = float_len(bytes, integer_len(bytes)).unwrap();
+        if let Some(c) = unsigned[len..].chars().next() {
+            self.error(
+                match_.offset,
+                format!(
+                    "Float literal `{}` has an invalid decimal digit `{}`",
+                    match_.text, c
+                ),
+            );
+            return None;
+        }
+        let mantissa = match_.text.split(['e', 'E']).next().unwrap();
+        if mantissa.split('.').any(has_misplaced_separator) {
+            self.error(
+                match_.offset,
+                format!(
+                    "Float literal `{}` has a `_` which isn't between two digits",
+                    match_.text
+                ),
+            );
+            return None;
+        }
+        let value: f64 = match_.text.replace('_', "").parse().unwrap();
+        if value.is_finite() {
+            Some(lit(
+                TokenType::Float,
+                match_.text,
+                Literal::Float(value),
+                match_.offset,
+            ))
+        } else {
+            self.error(
+                match_.offset,
+                format!("Float literal `{}` is out of range", match_.text),
+            );
+            None
+        }
+    }
+
+    fn next_match(&self) -> Option<Match<'a>> {
+        let text = &self.source[self.offset..];
+        let rest = text.as_bytes();
+        let next = rest.get(1).copied();
+
+        let (token_type, len) = match rest[0] {
+            b'0'..=b'9' => number(text),
+            b'-' if matches!(next, Some(b'0'..=b'9')) => {
+                let (token_type, len) = number(&text[1..]);
+                (token_type, 1 + len)
+            }
+            b' ' | b'\t' => (
+                TokenType::Space,
+                count_while(rest, |b| b == b' ' || b == b'\t'),
+            ),
+            b'\n' => (TokenType::Newline, 1),
+            b'\r' if next == Some(b'\n') => (TokenType::Newline, 2),
+            b'a'..=b'z' => word(text),
+            b'A'..=b'Z' => (TokenType::Constant, name_len(text)),
+            b'"' if rest.starts_with(MULTILINE_QUOTE.as_bytes()) => {
+                (TokenType::String, multiline_string_len(rest)?)
+            }
+            b'"' => match string_len(rest)? {
+                (len, false) => (TokenType::String, len),
+                (len, true) => (TokenType::InterpolationStart, len),
+            },
+            b':' => match next {
+                Some(b':') => (TokenType::DoubleColon, 2),
+                _ if text[1..].starts_with(is_identifier_start) => {
+                    (TokenType::Symbol, 1 + word_len(&text[1..]))
+                }
+                _ => return None,
+            },
+            b'-' if next == Some(b'>') => (TokenType::Arrow, 2),
+            b'+' | b'-' | b'\\' | b'/' | b'*' | b'%' => (TokenType::Identifier, 1),
+            b'<' | b'>' if next == Some(b'=') => (TokenType::Identifier, 2),
+            b'<' | b'>' => (TokenType::Identifier, 1),
+            b'=' if next == Some(b'=') => (TokenType::Identifier, 2),
+            b'(' => (TokenType::LeftParen, 1),
+            b')' => (TokenType::RightParen, 1),
+            b'[' => (TokenType::LeftBracket, 1),
+            b']' => (TokenType::RightBracket, 1),
+            b'{' => (TokenType::LeftBrace, 1),
+            b'}' if self.closes_interpolation() => match string_len(rest)? {
+                (len, false) => (TokenType::InterpolationEnd, len),
+                (len, true) => (TokenType::InterpolationMiddle, len),
+            },
+            b'}' => (TokenType::RightBrace, 1),
+            b'.' => (TokenType::Dot, 1),
+            b'&' => (TokenType::Ref, 1),
+            b',' => (TokenType::Comma, 1),
+            b'#' if self.offset == 0 && next == Some(b'!') => (
+                TokenType::Shebang,
+                count_while(rest, |b| b != b'\r' && b != b'\n'),
+            ),
+            b'#' if self.comments => (
+                TokenType::Comment,
+                count_while(rest, |b| b != b'\r' && b != b'\n'),
+            ),
+            // Past ASCII, names may use any letters, with uppercase ones starting a constant
+            _ => match text.chars().next().unwrap() {
+                c if is_identifier_start(c) => word(text),
+                c if c.is_uppercase() => (TokenType::Constant, name_len(text)),
+                _ => return None,
+            },
+        };
+
+        Some(Match::new(
+            token_type,
+            &self.source[self.offset..self.offset + len],
+            self.offset,
+        ))
+    }
+
+    fn closes_interpolation(&self) -> bool {
+        matches!(
+            self.interpolations.last(),
+            Some(Interpolation { open_braces: 0, .. })
+        )
     }
 
     fn at_end(&self) -> bool {
         self.offset == self.source.len()
     }
 }
+
+// Re-scans the gap between two tokens. Besides whitespace, commas and comments, the gap can hold
+// the newlines that end doc comment lines.
+fn trivia(source: &str, start: usize, end: usize) -> Vec<Sexp<'_, TokenType>> {
+    let mut lexer = Lexer::from_offset(&source[..end], start);
+    let mut trivia = vec![];
+    while !lexer.at_end() {
+        let match_ = lexer
+            .next_match()
+            .expect("text between tokens should only contain trivia");
+        lexer.offset += match_.len();
+        trivia.push(t(match_.token_type, match_.text, match_.offset));
+    }
+    trivia
+}
+
+fn count_while<P: Fn(u8) -> bool>(bytes: &[u8], predicate: P) -> usize {
+    bytes
+        .iter()
+        .position(|&b| !predicate(b))
+        .unwrap_or(bytes.len())
+}
+
+fn number(text: &str) -> (TokenType, usize) {
+    let bytes = text.as_bytes();
+    let len = integer_len(bytes);
+    let (token_type, len) = match float_len(bytes, len) {
+        Some(len) => (TokenType::Float, len),
+        None => (TokenType::Integer, len),
+    };
+    // Letters or digits straight after a number are part of it, so that e.g. `12abc` is reported
+    // as malformed rather than lexed as `12` followed by `abc`
+    (token_type, len + name_len(&text[len..]))
+}
+
+// Floats are decimal digits with a fractional part and an optional exponent, e.g. `1.5` or
+// `2.5e-3`. A `.` which isn't followed by a digit is not part of the number.
+fn float_len(bytes: &[u8], integer_len: usize) -> Option<usize> {
+    let is_decimal = |b: u8| b.is_ascii_digit() || b == b'_';
+    if !bytes[..integer_len].iter().all(|&b| is_decimal(b)) {
+        return None;
+    }
+
+    let fraction = match &bytes[integer_len..] {
+        [b'.', digit, ..] if digit.is_ascii_digit() => &bytes[integer_len + 1..],
+        _ => return None,
+    };
+    let len = integer_len + 1 + count_while(fraction, is_decimal);
+
+    let exponent = match &bytes[len..] {
+        [b'e' | b'E', b'+' | b'-', digit, ..] if digit.is_ascii_digit() => 2,
+        [b'e' | b'E', digit, ..] if digit.is_ascii_digit() => 1,
+        _ => return Some(len),
+    };
+    Some(len + exponent + count_while(&bytes[len + exponent..], |b| b.is_ascii_digit()))
+}
+
+// Integers are decimal, or hexadecimal/binary with a `0x`/`0b` prefix, and may contain `_`
+// separators between digits. A prefixed integer runs on over any letters or digits after it, so
+// that e.g. `0b102` is reported as malformed rather than lexed as `0b10` followed by `2`.
+fn integer_len(bytes: &[u8]) -> usize {
+    match bytes {
+        [b'0', b'x' | b'b', rest @ ..] => {
+            2 + count_while(rest, |b| b.is_ascii_alphanumeric() || b == b'_')
+        }
+        _ => count_while(bytes, |b| b.is_ascii_digit() || b == b'_'),
+    }
+}
+
+// Describes what's wrong with the digits of an integer literal (after any prefix), if anything
+fn malformed_digits(digits: &str, radix: u32) -> Option<String> {
+    if digits.chars().all(|c| c == '_') {
+        return Some("has no digits after its prefix".to_owned());
+    }
+    if let Some(digit) = digits.chars().find(|&c| c != '_' && !c.is_digit(radix)) {
+        let base = match radix {
+            2 => "binary",
+            16 => "hexadecimal",
+            _ => "decimal",
+        };
+        return Some(format!("has an invalid {} digit `{}`", base, digit));
+    }
+    if has_misplaced_separator(digits) {
+        return Some("has a `_` which isn't between two digits".to_owned());
+    }
+    None
+}
+
+// Whether a `_` in a run of digits doesn't separate two digits, as in `1_`, `1__0` or `0x_1`
+fn has_misplaced_separator(digits: &str) -> bool {
+    digits.starts_with('_') || digits.ends_with('_') || digits.contains("__")
+}
+
+// Identifiers, keywords and symbol names start with any letter that isn't uppercase, so names in
+// scripts without case (e.g. `名前`) are identifiers too
+fn is_identifier_start(c: char) -> bool {
+    c.is_alphabetic() && !c.is_uppercase()
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// The byte length of the letters, digits and underscores at the start of `text`
+fn name_len(text: &str) -> usize {
+    text.find(|c| !is_name_char(c)).unwrap_or(text.len())
+}
+
+// Lowercase words (identifiers, keywords and symbol names) may end in a single `?`
+fn word_len(text: &str) -> usize {
+    let len = name_len(text);
+    if text[len..].starts_with('?') {
+        len + 1
+    } else {
+        len
+    }
+}
+
+fn word(text: &str) -> (TokenType, usize) {
+    let len = word_len(text);
+    let token_type = TokenType::keyword(&text[..len]).unwrap_or(TokenType::Identifier);
+    (token_type, len)
+}
+
+// The length of the string literal at the start of `bytes` including both delimiters, or `None`
+// if it is never closed. The literal may instead stop at a `#{` starting an interpolation, in
+// which case `true` is returned alongside the length.
+fn string_len(bytes: &[u8]) -> Option<(usize, bool)> {
+    let mut index = 1;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'"' => return Some((index + 1, false)),
+            b'#' if bytes.get(index + 1) == Some(&b'{') => return Some((index + 2, true)),
+            b'\\' => index += 2,
+            _ => index += 1,
+        }
+    }
+    None
+}
+
+const MULTILINE_QUOTE: &str = "\"\"\"";
+
+// The length of the triple-quoted string at the start of `bytes` including both sets of quotes,
+// or `None` if it is never closed. Triple-quoted strings aren't interpolated.
+fn multiline_string_len(bytes: &[u8]) -> Option<usize> {
+    let quote = MULTILINE_QUOTE.as_bytes();
+    let mut index = quote.len();
+    while index < bytes.len() {
+        match bytes[index] {
+            b'"' if bytes[index..].starts_with(quote) => return Some(index + quote.len()),
+            b'\\' => index += 2,
+            _ => index += 1,
+        }
+    }
+    None
+}
+
+// Splits the body of a triple-quoted string into lines, each paired with the byte index at which
+// it starts within `text`. When the opening or closing quotes are on lines of their own, those
+// lines aren't part of the string, and the indentation common to every non-blank line is
+// removed, so the string can be indented along with the code around it.
+fn dedent(text: &str) -> Vec<(usize, &str)> {
+    let is_blank = |line: &str| line.trim_start_matches([' ', '\t']).is_empty();
+
+    let mut index = 0;
+    let mut lines: Vec<(usize, &str)> = text
+        .split('\n')
+        .map(|line| {
+            let start = index;
+            index += line.len() + 1;
+            (start, line.strip_suffix('\r').unwrap_or(line))
+        })
+        .collect();
+    if lines.len() == 1 {
+        return lines;
+    }
+    if is_blank(lines[lines.len() - 1].1) {
+        lines.pop();
+    }
+    if is_blank(lines[0].1) {
+        lines.remove(0);
+    }
+
+    let indentation = lines
+        .iter()
+        .filter(|(_, line)| !is_blank(line))
+        .map(|(_, line)| line.len() - line.trim_start_matches([' ', '\t']).len())
+        .min()
+        .unwrap_or(0);
+    lines
+        .into_iter()
+        .map(|(start, line)| match line.get(indentation..) {
+            Some(rest) if !is_blank(line) => (start + indentation, rest),
+            _ => (start + line.len(), ""),
+        })
+        .collect()
+}
+
+fn is_whitespace(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\r' | '\n' | ',')
+}
+
+/// Decodes the escape sequences in the body of a string literal. On failure, returns the byte
+/// index of the offending escape within `text` along with a description of the problem.
+fn unescape(text: &str) -> Result<String, (usize, String)> {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.char_indices();
+
+    while let Some((index, c)) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some((_, '"')) => result.push('"'),
+            Some((_, 'n')) => result.push('\n'),
+            Some((_, 't')) => result.push('\t'),
+            Some((_, '\\')) => result.push('\\'),
+            Some((_, '#')) => result.push('#'),
+            Some((_, 'u')) => result.push(unescape_unicode(&mut chars).ok_or_else(|| {
+                (
+                    index,
+                    "Invalid unicode escape, expected \\u{XXXX}".to_owned(),
+                )
+            })?),
+            Some((_, other)) => {
+                return Err((index, format!("Unknown escape sequence `\\{}`", other)))
+            }
+            None => return Err((index, "Unterminated escape sequence".to_owned())),
+        }
+    }
+
+    Ok(result)
+}
+
+fn unescape_unicode(chars: &mut std::str::CharIndices) -> Option<char> {
+    if chars.next()?.1 != '{' {
+        return None;
+    }
+
+    let mut code = String::new();
+    loop {
+        match chars.next()?.1 {
+            '}' => break,
+            c if c.is_ascii_hexdigit() && code.len() < 6 => code.push(c),
+            _ => return None,
+        }
+    }
+
+    std::char::from_u32(u32::from_str_radix(&code, 16).ok()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::sexp::Span;
+
+    fn tokenise(source: &str) -> Vec<Sexp<'_, TokenType>> {
+        Lexer::new(source).tokenise().unwrap()
+    }
+
+    fn errors(source: &str) -> Vec<LexError> {
+        Lexer::new(source).tokenise().unwrap_err()
+    }
+
+    fn string_value(token: &Sexp<TokenType>) -> String {
+        match token.literal() {
+            Some(Literal::String(value)) => value.clone(),
+            other => panic!("expected a string literal, got {:?}", other),
+        }
+    }
+
+    fn types(tokens: &[Sexp<'_, TokenType>]) -> Vec<TokenType> {
+        tokens.iter().map(|token| token.type_()).collect()
+    }
+
+    #[test]
+    fn tokenises_keywords() {
+        let tokens = tokenise(
+            "def defn deftype defmodule do else end false fn if import nil rescue true try unless \
+             when",
+        );
+        assert_eq!(
+            types(&tokens),
+            vec![
+                TokenType::Def,
+                TokenType::Defn,
+                TokenType::Deftype,
+                TokenType::Defmodule,
+                TokenType::Do,
+                TokenType::Else,
+                TokenType::End,
+                TokenType::False,
+                TokenType::Fn,
+                TokenType::If,
+                TokenType::Import,
+                TokenType::Nil,
+                TokenType::Rescue,
+                TokenType::True,
+                TokenType::Try,
+                TokenType::Unless,
+                TokenType::When,
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenises_identifiers_starting_with_keywords() {
+        let tokens = tokenise("define ending fns if? do_it");
+        assert_eq!(types(&tokens), vec![TokenType::Identifier; 5]);
+    }
+
+    #[test]
+    fn tokenises_a_definition() {
+        let tokens = tokenise("def foo 12");
+        assert_eq!(
+            types(&tokens),
+            vec![TokenType::Def, TokenType::Identifier, TokenType::Integer]
+        );
+        assert_eq!(tokens[1].text_content(), Some("foo"));
+        assert_eq!(tokens[2].offset(), 8);
+    }
+
+    #[test]
+    fn tokenises_a_symbol() {
+        let tokens = tokenise(":foo :empty?");
+        assert_eq!(types(&tokens), vec![TokenType::Symbol; 2]);
+        assert_eq!(tokens[0].text_content(), Some(":foo"));
+        assert_eq!(
+            tokens[0].literal(),
+            Some(&Literal::Symbol("foo".to_owned()))
+        );
+        assert_eq!(
+            tokens[1].literal(),
+            Some(&Literal::Symbol("empty?".to_owned()))
+        );
+    }
+
+    #[test]
+    fn tokenises_constants() {
+        let tokens = tokenise("deftype MyError Error");
+        assert_eq!(
+            types(&tokens),
+            vec![TokenType::Deftype, TokenType::Constant, TokenType::Constant]
+        );
+        assert_eq!(tokens[1].text_content(), Some("MyError"));
+    }
+
+    #[test]
+    fn tokenises_a_parenthesized_call() {
+        let tokens = tokenise("foo (+ 1 1) 1");
+        assert_eq!(
+            types(&tokens),
+            vec![
+                TokenType::Identifier,
+                TokenType::LeftParen,
+                TokenType::Identifier,
+                TokenType::Integer,
+                TokenType::Integer,
+                TokenType::RightParen,
+                TokenType::Integer,
+            ]
+        );
+        assert_eq!(tokens[5].offset(), 10);
+    }
+
+    #[test]
+    fn tokenises_arrays_and_hashmaps() {
+        let tokens = tokenise("[1 2] {:foo 1}");
+        assert_eq!(
+            types(&tokens),
+            vec![
+                TokenType::LeftBracket,
+                TokenType::Integer,
+                TokenType::Integer,
+                TokenType::RightBracket,
+                TokenType::LeftBrace,
+                TokenType::Symbol,
+                TokenType::Integer,
+                TokenType::RightBrace,
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_commas_as_whitespace() {
+        let tokens = tokenise("{:foo 1, :bar 2}");
+        assert_eq!(
+            types(&tokens),
+            vec![
+                TokenType::LeftBrace,
+                TokenType::Symbol,
+                TokenType::Integer,
+                TokenType::Symbol,
+                TokenType::Integer,
+                TokenType::RightBrace,
+            ]
+        );
+        assert_eq!(tokens[3].offset(), 9);
+    }
+
+    #[test]
+    fn tokenises_an_arrow_function() {
+        let tokens = tokenise("fn a -> - a 1");
+        assert_eq!(
+            types(&tokens),
+            vec![
+                TokenType::Fn,
+                TokenType::Identifier,
+                TokenType::Arrow,
+                TokenType::Identifier,
+                TokenType::Identifier,
+                TokenType::Integer,
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenises_module_scoped_names_and_references() {
+        let tokens = tokenise("M.foo M::Foo &String");
+        assert_eq!(
+            types(&tokens),
+            vec![
+                TokenType::Constant,
+                TokenType::Dot,
+                TokenType::Identifier,
+                TokenType::Constant,
+                TokenType::DoubleColon,
+                TokenType::Constant,
+                TokenType::Ref,
+                TokenType::Constant,
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_comments() {
+        let tokens = tokenise("# this is a comment");
+        assert!(tokens.is_empty());
+
+        let tokens = tokenise("foo 1 # trailing\n# own line\nbar");
+        assert_eq!(
+            types(&tokens),
+            vec![
+                TokenType::Identifier,
+                TokenType::Integer,
+                TokenType::Newline,
+                TokenType::Newline,
+                TokenType::Identifier,
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_comments_preceding_definitions_as_doc_comments() {
+        let tokens = tokenise("foo\n  # Adds one\n  #   to n\n  defn inc n -> + n 1");
+        assert_eq!(
+            types(&tokens[..5]),
+            vec![
+                TokenType::Identifier,
+                TokenType::Newline,
+                TokenType::DocComment,
+                TokenType::DocComment,
+                TokenType::Defn,
+            ]
+        );
+        assert_eq!(tokens[2].text_content(), Some("# Adds one"));
+        assert_eq!(
+            tokens[2].literal(),
+            Some(&Literal::String("Adds one".to_owned()))
+        );
+        assert_eq!(
+            tokens[3].literal(),
+            Some(&Literal::String("  to n".to_owned()))
+        );
+    }
+
+    #[test]
+    fn does_not_document_across_blank_lines_or_from_trailing_comments() {
+        let tokens = tokenise("# Unrelated\n\ndeftype Foo\nfoo # trailing\ndefmodule Bar do end");
+        assert!(!types(&tokens).contains(&TokenType::DocComment));
+        assert_eq!(
+            types(&tokens[..3]),
+            vec![TokenType::Newline, TokenType::Newline, TokenType::Deftype]
+        );
+    }
+
+    fn integer_value(token: &Sexp<TokenType>) -> i64 {
+        match token.literal() {
+            Some(Literal::Integer(value)) => *value,
+            other => panic!("expected an integer literal, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn tokenises_integer_literals() {
+        let tokens = tokenise("12 -5 0xFF 0b1010 1_000_000 -0x10 9223372036854775807");
+        assert_eq!(types(&tokens), vec![TokenType::Integer; 7]);
+        let values: Vec<i64> = tokens.iter().map(integer_value).collect();
+        assert_eq!(
+            values,
+            vec![12, -5, 255, 10, 1_000_000, -16, 9223372036854775807]
+        );
+        assert_eq!(tokens[4].text_content(), Some("1_000_000"));
+    }
+
+    #[test]
+    fn tokenises_float_literals() {
+        let tokens = tokenise("1.5 -0.25 1_000.5 2.5e3 1.0E-2 Foo.bar 1.foo");
+        let values: Vec<Option<&Literal>> = tokens[..5].iter().map(|t| t.literal()).collect();
+        assert_eq!(
+            values,
+            vec![
+                Some(&Literal::Float(1.5)),
+                Some(&Literal::Float(-0.25)),
+                Some(&Literal::Float(1000.5)),
+                Some(&Literal::Float(2500.0)),
+                Some(&Literal::Float(0.01)),
+            ]
+        );
+        assert_eq!(
+            types(&tokens[5..]),
+            vec![
+                TokenType::Constant,
+                TokenType::Dot,
+                TokenType::Identifier,
+                TokenType::Integer,
+                TokenType::Dot,
+                TokenType::Identifier,
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_out_of_range_floats() {
+        let errors = errors("1.0e999");
+        assert_eq!(
+            errors,
+            vec![LexError::new(
+                0,
+                '1',
+                "Float literal `1.0e999` is out of range".to_owned()
+            )]
+        );
+    }
+
+    #[test]
+    fn tokenises_minus_followed_by_a_space_as_an_identifier() {
+        let tokens = tokenise("- 5 -> -");
+        assert_eq!(
+            types(&tokens),
+            vec![
+                TokenType::Identifier,
+                TokenType::Integer,
+                TokenType::Arrow,
+                TokenType::Identifier,
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_out_of_range_integers() {
+        let errors = errors("9223372036854775808 -9223372036854775809");
+        assert_eq!(
+            errors,
+            vec![
+                LexError::new(
+                    0,
+                    '9',
+                    "Integer literal `9223372036854775808` is out of range for a 64-bit integer"
+                        .to_owned()
+                ),
+                LexError::new(
+                    20,
+                    '-',
+                    "Integer literal `-9223372036854775809` is out of range for a 64-bit integer"
+                        .to_owned()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_malformed_integers() {
+        let message = |source: &str| errors(source)[0].message.clone();
+        assert_eq!(
+            message("0b102"),
+            "Integer literal `0b102` has an invalid binary digit `2`"
+        );
+        assert_eq!(
+            message("0xFG"),
+            "Integer literal `0xFG` has an invalid hexadecimal digit `G`"
+        );
+        for literal in ["0x", "0b", "-0x", "0x_"] {
+            assert_eq!(
+                message(literal),
+                format!(
+                    "Integer literal `{}` has no digits after its prefix",
+                    literal
+                )
+            );
+        }
+        for literal in ["1_", "1__2", "0x_1", "0b1_", "-1_000__000"] {
+            assert_eq!(
+                message(literal),
+                format!(
+                    "Integer literal `{}` has a `_` which isn't between two digits",
+                    literal
+                )
+            );
+        }
+        for (literal, digit) in [("12abc", 'a'), ("1e5", 'e'), ("-3x", 'x'), ("7é", 'é')] {
+            assert_eq!(
+                message(literal),
+                format!(
+                    "Integer literal `{}` has an invalid decimal digit `{}`",
+                    literal, digit
+                )
+            );
+        }
+        for literal in ["1_.5", "1.5_", "1__0.5", "1.0__1e3"] {
+            assert_eq!(
+                message(literal),
+                format!(
+                    "Float literal `{}` has a `_` which isn't between two digits",
+                    literal
+                )
+            );
+        }
+        for (literal, digit) in [("1.5abc", 'a'), ("2.0e", 'e'), ("1.0e1_0", '_')] {
+            assert_eq!(
+                message(literal),
+                format!(
+                    "Float literal `{}` has an invalid decimal digit `{}`",
+                    literal, digit
+                )
+            );
+        }
+        assert_eq!(errors("0b102 1").len(), 1);
+        assert_eq!(errors("12abc 1e5 x").len(), 2);
+    }
+
+    #[test]
+    fn tokens_span_their_source_text() {
+        let tokens = tokenise("foo \"bär\" :baz");
+        let spans: Vec<Span> = tokens.iter().map(|token| token.span()).collect();
+        assert_eq!(
+            spans,
+            vec![Span::new(0, 3), Span::new(4, 10), Span::new(11, 15)]
+        );
+    }
+
+    #[test]
+    fn tokenises_a_string() {
+        let tokens = tokenise(r#" "hello world" "#);
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].type_(), TokenType::String);
+        assert_eq!(tokens[0].text_content(), Some(r#""hello world""#));
+        assert_eq!(tokens[0].offset(), 1);
+        assert_eq!(string_value(&tokens[0]), "hello world");
+    }
+
+    #[test]
+    fn tokenises_subsequent_strings() {
+        let tokens = tokenise(r#""hello" "world""#);
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(string_value(&tokens[0]), "hello");
+        assert_eq!(string_value(&tokens[1]), "world");
+        assert_eq!(tokens[1].offset(), 8);
+    }
+
+    #[test]
+    fn decodes_escape_sequences() {
+        let tokens = tokenise(r#""a \"quote\"\n\tb \\ \u{e9}\u{1F600}""#);
+        assert_eq!(
+            string_value(&tokens[0]),
+            "a \"quote\"\n\tb \\ \u{e9}\u{1F600}"
+        );
+    }
+
+    #[test]
+    fn reports_unterminated_strings() {
+        let errors = errors("println \"oops\nfoo @");
+        assert_eq!(
+            errors,
+            vec![
+                LexError::new(8, '"', "Unterminated string".to_owned()),
+                LexError::new(18, '@', "Unexpected input `@`".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_unknown_escapes() {
+        let errors = errors(r#""a\q""#);
+        assert_eq!(
+            errors,
+            vec![LexError::new(
+                2,
+                '\\',
+                "Unknown escape sequence `\\q`".to_owned()
+            )]
+        );
+    }
+
+    #[test]
+    fn tokenises_interpolated_strings() {
+        let tokens = tokenise(r#""Hello #{name}, you are #{+ age 1}!""#);
+        assert_eq!(
+            types(&tokens),
+            vec![
+                TokenType::InterpolationStart,
+                TokenType::Identifier,
+                TokenType::InterpolationMiddle,
+                TokenType::Identifier,
+                TokenType::Identifier,
+                TokenType::Integer,
+                TokenType::InterpolationEnd,
+            ]
+        );
+        assert_eq!(tokens[0].text_content(), Some("\"Hello #{"));
+        assert_eq!(tokens[2].text_content(), Some("}, you are #{"));
+        assert_eq!(tokens[6].text_content(), Some("}!\""));
+        let segments: Vec<_> = [&tokens[0], &tokens[2], &tokens[6]]
+            .iter()
+            .map(|token| string_value(token))
+            .collect();
+        assert_eq!(segments, vec!["Hello ", ", you are ", "!"]);
+    }
+
+    #[test]
+    fn tokenises_braces_and_strings_inside_interpolations() {
+        let tokens = tokenise(r##""a #{get {:b "#{c}"} :b} d""##);
+        assert_eq!(
+            types(&tokens),
+            vec![
+                TokenType::InterpolationStart,
+                TokenType::Identifier,
+                TokenType::LeftBrace,
+                TokenType::Symbol,
+                TokenType::InterpolationStart,
+                TokenType::Identifier,
+                TokenType::InterpolationEnd,
+                TokenType::RightBrace,
+                TokenType::Symbol,
+                TokenType::InterpolationEnd,
+            ]
+        );
+        assert_eq!(string_value(&tokens[9]), " d");
+    }
+
+    #[test]
+    fn escaped_interpolations_are_plain_strings() {
+        let tokens = tokenise(r#""\#{name}" }"#);
+        assert_eq!(
+            types(&tokens),
+            vec![TokenType::String, TokenType::RightBrace]
+        );
+        assert_eq!(string_value(&tokens[0]), "#{name}");
+    }
+
+    #[test]
+    fn reports_unterminated_interpolations() {
+        assert_eq!(
+            errors("\"a #{b\nc"),
+            vec![LexError::new(0, '"', "Unterminated string".to_owned())]
+        );
+        assert_eq!(
+            errors("\"a #{b} c\n1"),
+            vec![LexError::new(6, '}', "Unterminated string".to_owned())]
+        );
+    }
+
+    #[test]
+    fn tokenises_multiline_strings() {
+        let source = "foo \"\"\"\n    Dear \"#{name}\",\n\n      Hello!\\n\n    \"\"\"\n1";
+        let tokens = tokenise(source);
+        assert_eq!(
+            types(&tokens),
+            vec![
+                TokenType::Identifier,
+                TokenType::String,
+                TokenType::Newline,
+                TokenType::Integer
+            ]
+        );
+        assert_eq!(string_value(&tokens[1]), "Dear \"#{name}\",\n\n  Hello!\n");
+        assert_eq!(tokens[1].span(), Span::new(4, source.len() - 2));
+    }
+
+    #[test]
+    fn tokenises_single_line_triple_quoted_strings() {
+        let tokens = tokenise(r#""""say "hi" now""" """ """"#);
+        assert_eq!(string_value(&tokens[0]), "say \"hi\" now");
+        assert_eq!(string_value(&tokens[1]), " ");
+    }
+
+    #[test]
+    fn reports_errors_inside_multiline_strings_at_their_source_offset() {
+        let source = "\"\"\"\n    fine\n    not \\q fine\n    \"\"\"";
+        assert_eq!(
+            errors(source),
+            vec![LexError::new(
+                source.find('\\').unwrap(),
+                '\\',
+                "Unknown escape sequence `\\q`".to_owned()
+            )]
+        );
+        assert_eq!(
+            errors("1 \"\"\"\nabc\n\"\" 2\n"),
+            vec![LexError::new(2, '"', "Unterminated string".to_owned())]
+        );
+    }
+
+    #[test]
+    fn tokenises_unicode_names() {
+        let tokens = tokenise("café naïve? :día 名前 Ωmega ÉTÉ");
+        assert_eq!(
+            types(&tokens),
+            vec![
+                TokenType::Identifier,
+                TokenType::Identifier,
+                TokenType::Symbol,
+                TokenType::Identifier,
+                TokenType::Constant,
+                TokenType::Constant,
+            ]
+        );
+        let texts: Vec<_> = tokens.iter().map(|token| token.text_content()).collect();
+        assert_eq!(
+            texts,
+            vec![
+                Some("café"),
+                Some("naïve?"),
+                Some(":día"),
+                Some("名前"),
+                Some("Ωmega"),
+                Some("ÉTÉ"),
+            ]
+        );
+        assert_eq!(
+            tokens[2].literal(),
+            Some(&Literal::Symbol("día".to_owned()))
+        );
+        assert_eq!(tokens[3].span(), Span::new(20, 26));
+    }
+
+    #[test]
+    fn tokenises_multibyte_strings_and_comments() {
+        let tokens = tokenise("# héllo 👋\n\"ünïcödé 😀\" \"#{café}✓\" 1");
+        assert_eq!(
+            types(&tokens),
+            vec![
+                TokenType::Newline,
+                TokenType::String,
+                TokenType::InterpolationStart,
+                TokenType::Identifier,
+                TokenType::InterpolationEnd,
+                TokenType::Integer,
+            ]
+        );
+        assert_eq!(string_value(&tokens[1]), "ünïcödé 😀");
+        assert_eq!(tokens[1].span(), Span::new(14, 32));
+        assert_eq!(string_value(&tokens[4]), "✓");
+        assert_eq!(tokens[5].offset(), 47);
+    }
+
+    #[test]
+    fn reports_unexpected_multibyte_characters() {
+        let errors = errors("1 € 2 →x 3");
+        assert_eq!(
+            errors,
+            vec![
+                LexError::new(2, '€', "Unexpected input `€`".to_owned()),
+                LexError::new(8, '→', "Unexpected input `→`".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn lexes_any_slice_of_multibyte_source_without_panicking() {
+        let source = "defn grüß name -> \"Grüß #{name} 👋\" # ✓\n\"\"\"\n  日本\n  \"\"\" € :día";
+        for (index, _) in source.char_indices() {
+            let _ = Lexer::new(&source[..index]).tokenise();
+            let _ = Lexer::new(&source[index..]).tokenise();
+        }
+    }
+
+    #[test]
+    fn skips_a_leading_shebang_line() {
+        let tokens = tokenise("#!/usr/bin/env emerald\ndefn main -> 1");
+        assert_eq!(
+            types(&tokens),
+            vec![
+                TokenType::Newline,
+                TokenType::Defn,
+                TokenType::Identifier,
+                TokenType::Arrow,
+                TokenType::Integer,
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_a_leading_shebang_line_without_comment_handling() {
+        let source = "#!/usr/bin/env emerald\ndefn main -> 1";
+        let tokens = Lexer::new(source).without_comments().tokenise().unwrap();
+        assert_eq!(format!("{:?}", tokens), format!("{:?}", tokenise(source)));
+        assert_eq!(types(&tokens)[..2], [TokenType::Newline, TokenType::Defn]);
+
+        let errors = Lexer::new("#!/usr/bin/env emerald\n# main\n1")
+            .without_comments()
+            .tokenise()
+            .unwrap_err();
+        assert_eq!(
+            errors,
+            vec![LexError::new(23, '#', "Unexpected input `#`".to_owned())]
+        );
+    }
+
+    #[test]
+    fn treats_later_shebangs_as_comments() {
+        let tokens = tokenise("1\n#! docs\ndefn main -> 1");
+        assert_eq!(tokens[2].type_(), TokenType::DocComment);
+        assert_eq!(string_value(&tokens[2]), "! docs");
+    }
+
+    fn describe(result: Result<Token<'_>, LexError>) -> String {
+        match result {
+            Ok(token) => format!("{:?}", token),
+            Err(error) => format!("error at {}: {}", error.offset, error),
+        }
+    }
+
+    #[test]
+    fn iterates_over_tokens_and_errors_in_source_order() {
+        let results: Vec<_> = Lexer::new("1 € 2").map(describe).collect();
+        assert_eq!(
+            results,
+            vec![
+                "s(Integer, \"1\", Integer(1), span: 0..1)",
+                "error at 2: Unexpected input `€`",
+                "s(Integer, \"2\", Integer(2), span: 6..7)",
+            ]
+        );
+    }
+
+    #[test]
+    fn lexes_lazily() {
+        let mut lexer = Lexer::new("foo \"never closed");
+        assert_eq!(lexer.next().unwrap().unwrap().text_content(), Some("foo"));
+        assert!(lexer.next().unwrap().is_err());
+        assert!(lexer.next().is_none());
+    }
+
+    #[test]
+    fn resumes_lexing_from_an_offset() {
+        let source = "defn foo -> 1\n# Greets\ndefn bar -> \"hi #{x}\"\n:done";
+        let line_start = source.find("# Greets").unwrap();
+        let from_start: Vec<_> = Lexer::new(source)
+            .map(describe)
+            .skip_while(|token| !token.contains("DocComment"))
+            .collect();
+        let resumed: Vec<_> = Lexer::from_offset(source, line_start)
+            .map(describe)
+            .collect();
+        assert_eq!(resumed, from_start);
+    }
+
+    #[test]
+    fn resumes_from_the_start_of_a_multibyte_character_containing_the_offset() {
+        let source = "foo grüß";
+        let inside = source.find('ü').unwrap() + 1;
+        let resumed: Vec<_> = Lexer::from_offset(source, inside).map(describe).collect();
+        assert_eq!(resumed, vec!["s(Identifier, \"üß\", span: 6..10)"]);
+    }
+
+    #[test]
+    fn relexes_an_edited_line() {
+        let source = "foo 1\nbar 2\nbaz 3";
+        let edited = source.replace("bar 2", "bar \"two\" 2.0");
+        let line_start = source.find("bar").unwrap();
+        let line: Vec<_> = Lexer::from_offset(&edited, line_start)
+            .map(Result::unwrap)
+            .take_while(|token| token.type_() != TokenType::Newline)
+            .collect();
+        assert_eq!(
+            types(&line),
+            vec![TokenType::Identifier, TokenType::String, TokenType::Float]
+        );
+        assert_eq!(line[1].span(), Span::new(10, 15));
+    }
+
+    #[test]
+    fn reports_every_invalid_token() {
+        let errors = errors("def @foo 1\nbar $ ^baz");
+        assert_eq!(
+            errors,
+            vec![
+                LexError::new(4, '@', "Unexpected input `@`".to_owned()),
+                LexError::new(15, '$', "Unexpected input `$`".to_owned()),
+                LexError::new(17, '^', "Unexpected input `^`".to_owned()),
+            ]
+        );
+        assert_eq!(errors[0].to_string(), "Unexpected input `@`");
+    }
+
+    #[test]
+    fn recovers_from_a_lone_carriage_return() {
+        let errors = errors("a \r b");
+        assert_eq!(
+            errors,
+            vec![LexError::new(2, '\r', "Unexpected input `\r`".to_owned())]
+        );
+    }
+
+    fn lossless(source: &str) -> Vec<Sexp<'_, TokenType>> {
+        Lexer::new(source).tokenise_lossless().unwrap()
+    }
+
+    fn trivia<'a, 'b>(token: &'b Sexp<'a, TokenType>) -> &'b [Sexp<'a, TokenType>] {
+        match token {
+            Sexp::Terminal(terminal) => &terminal.trivia,
+            Sexp::NonTerminal(_) => panic!("tokens are terminals"),
+        }
+    }
+
+    fn rebuild(tokens: &[Sexp<'_, TokenType>]) -> String {
+        let mut source = String::new();
+        for token in tokens {
+            for trivia in trivia(token) {
+                source += trivia.text_content().unwrap();
+            }
+            source += token.text_content().unwrap();
+        }
+        source
+    }
+
+    #[test]
+    fn attaches_trivia_to_the_following_token() {
+        let tokens = lossless("foo  1, # one\n  # Doc\n  defn x -> 2 ");
+        assert_eq!(
+            types(&tokens),
+            vec![
+                TokenType::Identifier,
+                TokenType::Integer,
+                TokenType::Newline,
+                TokenType::DocComment,
+                TokenType::Defn,
+                TokenType::Identifier,
+                TokenType::Arrow,
+                TokenType::Integer,
+                TokenType::Eof,
+            ]
+        );
+        assert_eq!(types(trivia(&tokens[1])), vec![TokenType::Space]);
+        assert_eq!(
+            types(trivia(&tokens[2])),
+            vec![TokenType::Comma, TokenType::Space, TokenType::Comment]
+        );
+        assert_eq!(types(trivia(&tokens[3])), vec![TokenType::Space]);
+        assert_eq!(
+            types(trivia(&tokens[4])),
+            vec![TokenType::Newline, TokenType::Space]
+        );
+        assert_eq!(types(trivia(&tokens[8])), vec![TokenType::Space]);
+    }
+
+    #[test]
+    fn lossless_tokens_round_trip_the_example_programs() {
+        let root = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("..");
+        let mut directories = vec![root.join("samples"), root.join("emerald")];
+        let mut checked = 0;
+
+        while let Some(directory) = directories.pop() {
+            for entry in std::fs::read_dir(directory).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    directories.push(path);
+                } else if path.extension() == Some(std::ffi::OsStr::new("em")) {
+                    let source = std::fs::read_to_string(&path).unwrap();
+                    assert_eq!(rebuild(&lossless(&source)), source, "{:?}", path);
+                    checked += 1;
+                }
+            }
+        }
+
+        assert!(checked > 0);
+    }
+
+    #[test]
+    fn lossless_tokens_round_trip_generated_programs() {
+        let fragments = [
+            "foo",
+            "Bar",
+            " ",
+            "  ",
+            "\t",
+            "\n",
+            "\r\n",
+            ",",
+            "1",
+            "-2",
+            "0xF",
+            "1.5",
+            "\"s\\n\"",
+            ":sym",
+            "(",
+            ")",
+            "[",
+            "]",
+            "{",
+            "}",
+            "->",
+            ".",
+            "::",
+            "&",
+            "# comment\n",
+            "# doc\n",
+            "defn",
+            "deftype",
+            "defmodule",
+            "do",
+            "end",
+            "+",
+            "==",
+            "<=",
+        ];
+
+        // A small xorshift generator keeps the generated programs reproducible
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        for _ in 0..500 {
+            let length = next() % 40;
+            let source: String = (0..length)
+                .map(|_| fragments[(next() % fragments.len() as u64) as usize])
+                .collect();
+            if let Ok(tokens) = Lexer::new(&source).tokenise_lossless() {
+                assert_eq!(rebuild(&tokens), source);
+            }
+        }
+    }
+
+    // The parts of a token the regex lexer also produced; its integers carried no value
+    fn summary(token: &Sexp<'_, TokenType>) -> (TokenType, String, usize, Option<Literal>) {
+        let literal = match token.type_() {
+            TokenType::Integer => None,
+            _ => token.literal().cloned(),
+        };
+        let text = token.text_content().unwrap().to_owned();
+        (token.type_(), text, token.offset(), literal)
+    }
+
+    fn assert_matches_regex_lexer(source: &str) {
+        let scanned: Vec<_> = tokenise(source).iter().map(summary).collect();
+        let matched: Vec<_> = regex_lexer::tokenise(source).iter().map(summary).collect();
+        assert_eq!(scanned, matched, "tokens differ for {:?}", source);
+    }
+
+    // Source made of random tokens from the syntax both lexers share, with whitespace, commas or
+    // comments between them (or nothing, next to brackets)
+    fn generate_source(seed: u64, token_count: usize) -> String {
+        const WORDS: &str = "foo bar_baz empty? x1 defn def do end fn if unless when else nil \
+                             true false import deftype defmodule + - * / % < <= > >= == -> \
+                             0 42 1234567 :foo :ok? Foo HTTP2 ( ) [ ] { } . :: &";
+        const SEPARATORS: &[&str] = &[" ", " ", "\t", ", ", "\n", "  # comment\n", "\n# doc\n"];
+        let tokens: Vec<_> = WORDS
+            .split_whitespace()
+            .chain([r#""""#, r#""hi there""#, r#""esc \n \" \\""#])
+            .collect();
+
+        // xorshift64, so failures are reproducible from the seed
+        let mut state = seed.max(1);
+        let mut next = |bound: usize| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % bound as u64) as usize
+        };
+
+        let is_bracket = |token: &str| "()[]{}".contains(token);
+        let mut source = String::new();
+        let mut previous = "\n";
+        for _ in 0..token_count {
+            let token = tokens[next(tokens.len())];
+            if !(is_bracket(previous) || is_bracket(token)) || next(2) == 0 {
+                source.push_str(SEPARATORS[next(SEPARATORS.len())]);
+            }
+            source.push_str(token);
+            previous = token;
+        }
+        source
+    }
+
+    #[test]
+    fn scanner_matches_the_regex_lexer_on_samples() {
+        for source in [
+            include_str!("../../../emerald/test/language_test.em"),
+            include_str!("../../../samples/fib.em"),
+            include_str!("../../../samples/sandbox.em"),
+            include_str!("../../../samples/test.em"),
+        ] {
+            assert_matches_regex_lexer(source);
+        }
+    }
+
+    #[test]
+    fn scanner_matches_the_regex_lexer_on_generated_sources() {
+        for seed in 1..=100 {
+            assert_matches_regex_lexer(&generate_source(seed, 200));
+        }
+        assert_matches_regex_lexer(&generate_source(0x5eed, 5_000));
+    }
+
+    // A benchmark rather than a test, as it times lexing several megabytes of source. Run it with
+    // `cargo test --release -- --ignored lexer_throughput`. Lexing 8 times as much source should
+    // take about 8 times as long, so the time per byte should stay roughly level, well under the
+    // 8x slowdown per byte that quadratic lexing would show. The fastest of a few runs is used to
+    // keep out noise.
+    #[test]
+    #[ignore]
+    fn lexer_throughput_is_linear() {
+        let seconds_per_byte = |source: &str| {
+            (0..3)
+                .map(|_| {
+                    let started = std::time::Instant::now();
+                    assert!(!tokenise(source).is_empty());
+                    started.elapsed().as_secs_f64()
+                })
+                .fold(f64::INFINITY, f64::min)
+                / source.len() as f64
+        };
+
+        let small = generate_source(1, 100_000);
+        let large = generate_source(2, 800_000);
+        let megabytes = |source: &str| source.len() as f64 / 1e6;
+        println!(
+            "lexing {:.1} MB, then {:.1} MB of source",
+            megabytes(&small),
+            megabytes(&large)
+        );
+        let (small, large) = (seconds_per_byte(&small), seconds_per_byte(&large));
+        println!(
+            "took {:.1} ns/byte, then {:.1} ns/byte",
+            small * 1e9,
+            large * 1e9
+        );
+        assert!(
+            large < small * 3.0,
+            "lexing slowed from {:.1} to {:.1} ns/byte as the input grew 8x",
+            small * 1e9,
+            large * 1e9
+        );
+    }
+}
@@ -0,0 +1,151 @@
+// The regex lexer the scanner replaced, kept so tests can check the scanner still produces the
+// same tokens for the syntax the two share. Unlike the original, each regex is compiled once, and
+// input it can't match panics rather than being reported.
+use super::{unescape, TokenType};
+use crate::compiler::sexp::{lit, t, Literal, Sexp};
+use regex::Regex;
+
+pub fn tokenise(source: &str) -> Vec<Sexp<'_, TokenType>> {
+    RegexLexer::new(source).tokenise()
+}
+
+fn rules() -> Vec<(TokenType, Regex)> {
+    [
+        (TokenType::Integer, r"\A[0-9]+"),
+        (TokenType::Space, r"\A[ \t]+"),
+        (TokenType::Newline, r"\A\n|\A\r\n"),
+        (
+            TokenType::Identifier,
+            r"\A[+\-\\/*%]|\A[><]=?|\A==|\A[a-z]+[a-zA-Z_0-9]*\??",
+        ),
+        (TokenType::String, r#"(?s)\A"(?:[^"\\]|\\.)*""#),
+        (TokenType::Symbol, r"\A:[a-z]+[a-zA-Z_0-9]*\??"),
+        (TokenType::Constant, r"\A[A-Z]+[a-zA-Z_0-9]*"),
+        (TokenType::LeftParen, r"\A\("),
+        (TokenType::RightParen, r"\A\)"),
+        (TokenType::LeftBracket, r"\A\["),
+        (TokenType::RightBracket, r"\A\]"),
+        (TokenType::LeftBrace, r"\A\{"),
+        (TokenType::RightBrace, r"\A\}"),
+        (TokenType::Arrow, r"\A->"),
+        (TokenType::Dot, r"\A\."),
+        (TokenType::DoubleColon, r"\A::"),
+        (TokenType::Ref, r"\A&"),
+        (TokenType::Comma, r"\A,"),
+        (TokenType::Comment, r"\A#[^\r\n]*"),
+    ]
+    .iter()
+    .map(|(token_type, pattern)| (*token_type, Regex::new(pattern).unwrap()))
+    .collect()
+}
+
+struct RegexLexer<'a> {
+    source: &'a str,
+    offset: usize,
+    rules: Vec<(TokenType, Regex)>,
+    at_line_start: bool,
+    doc_comments: Vec<Sexp<'a, TokenType>>,
+    doc_newlines: Vec<Sexp<'a, TokenType>>,
+}
+
+impl<'a> RegexLexer<'a> {
+    fn new(source: &'a str) -> Self {
+        RegexLexer {
+            source,
+            offset: 0,
+            rules: rules(),
+            at_line_start: true,
+            doc_comments: vec![],
+            doc_newlines: vec![],
+        }
+    }
+
+    fn tokenise(mut self) -> Vec<Sexp<'a, TokenType>> {
+        let mut result = vec![];
+
+        while self.offset < self.source.len() {
+            let (token_type, text) = self.longest_match();
+            let offset = self.offset;
+            self.offset += text.len();
+            match token_type {
+                TokenType::Comment => self.comment(text, offset),
+                TokenType::String => {
+                    let value = unescape(&text[1..text.len() - 1]).unwrap();
+                    self.push(
+                        lit(TokenType::String, text, Literal::String(value), offset),
+                        &mut result,
+                    );
+                }
+                TokenType::Symbol => self.push(
+                    lit(
+                        TokenType::Symbol,
+                        text,
+                        Literal::Symbol(text[1..].to_owned()),
+                        offset,
+                    ),
+                    &mut result,
+                ),
+                TokenType::Space | TokenType::Comma => {}
+                token_type => self.push(t(token_type, text, offset), &mut result),
+            }
+        }
+
+        self.flush_doc_comments(&mut result);
+        result
+    }
+
+    // Of the rules matching the longest text, the last one wins
+    fn longest_match(&self) -> (TokenType, &'a str) {
+        let rest = &self.source[self.offset..];
+        let (token_type, text) = self
+            .rules
+            .iter()
+            .filter_map(|(token_type, regex)| Some((*token_type, regex.find(rest)?.as_str())))
+            .max_by_key(|(_, text)| text.len())
+            .unwrap_or_else(|| panic!("no rule matches {:?}", rest));
+        match token_type {
+            TokenType::Identifier => (TokenType::keyword(text).unwrap_or(token_type), text),
+            _ => (token_type, text),
+        }
+    }
+
+    fn comment(&mut self, text: &'a str, offset: usize) {
+        if !self.at_line_start {
+            return;
+        }
+
+        let value = text[1..].strip_prefix(' ').unwrap_or(&text[1..]);
+        self.doc_comments.push(lit(
+            TokenType::DocComment,
+            text,
+            Literal::String(value.trim_end().to_owned()),
+            offset,
+        ));
+    }
+
+    fn push(&mut self, token: Sexp<'a, TokenType>, result: &mut Vec<Sexp<'a, TokenType>>) {
+        let token_type = token.type_();
+        if token_type == TokenType::Newline && self.doc_newlines.len() < self.doc_comments.len() {
+            self.doc_newlines.push(token);
+            return;
+        }
+
+        if matches!(
+            token_type,
+            TokenType::Defn | TokenType::Deftype | TokenType::Defmodule
+        ) {
+            result.append(&mut self.doc_comments);
+            self.doc_newlines.clear();
+        } else {
+            self.flush_doc_comments(result);
+        }
+
+        self.at_line_start = token_type == TokenType::Newline;
+        result.push(token);
+    }
+
+    fn flush_doc_comments(&mut self, result: &mut Vec<Sexp<'a, TokenType>>) {
+        self.doc_comments.clear();
+        result.append(&mut self.doc_newlines);
+    }
+}
@@ -1,27 +1,110 @@
 use super::file;
 use super::lexer;
-use super::sexp::{s, t, Sexp};
+use super::sexp::{lit, s, t, Literal, Sexp, Span};
 
 pub fn parse<'file: 'tokens, 'tokens, T: file::File<'file>>(
     file: &'file T,
     tokens: &'tokens Vec<Sexp<'tokens, lexer::TokenType>>,
-) -> Sexp<'tokens, Node> {
-    Parser::new(file, tokens).parse().unwrap()
+) -> Result<Ast<'tokens>, Vec<ParseError>> {
+    Parser::new(file, tokens).parse()
+}
+
+/// A parsed program, along with the files it imports so they can be loaded before it's compiled
+pub struct Ast<'a> {
+    pub root: Sexp<'a, Node>,
+    pub imports: Vec<Import>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Import {
+    pub path: String,
+    pub offset: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub span: Span,
+    pub kind: ParseErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    // Something other than any of the expected kinds of token or expression, e.g. `` `end` ``,
+    // `newline` or `end of input`
+    Unexpected {
+        expected: Vec<&'static str>,
+        found: String,
+    },
+    // Well-formed syntax which isn't allowed where it is, e.g. an import after other expressions
+    Invalid(String),
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            ParseErrorKind::Unexpected { expected, found } => {
+                let expected = match expected.split_last() {
+                    Some((last, [])) => last.to_string(),
+                    Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+                    None => "nothing".to_owned(),
+                };
+                write!(f, "Expected {}, got {}", expected, found)
+            }
+            ParseErrorKind::Invalid(message) => write!(f, "{}", message),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
 pub enum Node {
     Args,
+    Array,
     Block,
     Call,
+    Constant,
+    Constructor,
+    Def,
+    Defmodule,
+    Defn,
+    Deftype,
+    Doc,
+    DocComment,
+    Documented,
+    False,
+    Float,
+    Fn,
+    GuardElse,
+    Guards,
+    Hashmap,
     Identifier,
+    If,
+    Import,
     Integer,
+    Interpolation,
+    ModuleScopedConstant,
+    ModuleScopedIdentifier,
+    Nil,
+    Params,
+    Ref,
+    Rescue,
+    String,
+    Supertype,
+    Symbol,
+    SymbolCall,
+    True,
+    Try,
+    Unless,
+    When,
 }
 
 struct Parser<'file: 'tokens, 'tokens, T> {
     file: &'file T,
     tokens: &'tokens Vec<Sexp<'tokens, lexer::TokenType>>,
     position: usize,
+    errors: Vec<ParseError>,
+    // Set after a syntax error until the parser has skipped to the next expression, so the
+    // expressions the error cut short don't report it again
+    recovering: bool,
 }
 
 impl<'file: 'tokens, 'tokens, T: file::File<'file>> Parser<'file, 'tokens, T> {
@@ -30,75 +113,795 @@ impl<'file: 'tokens, 'tokens, T: file::File<'file>> Parser<'file, 'tokens, T> {
             file,
             tokens,
             position: 0,
+            errors: vec![],
+            recovering: false,
         }
     }
 
-    fn parse(&mut self) -> Option<Sexp<'tokens, Node>> {
-        let mut ast = s(Node::Block, vec![], self.position);
+    fn parse(mut self) -> Result<Ast<'tokens>, Vec<ParseError>> {
+        let mut root = s(Node::Block, vec![], self.position);
+        let mut imports = vec![];
+        let mut in_body = false;
 
         while !self.at_end() {
-            if let Some(node) = self.parse_expr() {
-                ast.push(node).unwrap();
+            // Expressions are separated by newlines, and blank lines (including the one left by a
+            // skipped shebang) may come before, between or after them
+            if self.matches(lexer::TokenType::Newline) {
+                continue;
+            }
+
+            let start = self.position;
+            if self.check(lexer::TokenType::Import) {
+                if let Some(import) = self.parse_import_expr() {
+                    if in_body {
+                        self.invalid(
+                            import.span(),
+                            "import must appear before other expressions".to_owned(),
+                        );
+                    }
+                    let path = &import.children()[0];
+                    if let Some(Literal::String(value)) = path.literal() {
+                        imports.push(Import {
+                            path: value.clone(),
+                            offset: path.offset(),
+                        });
+                    }
+                    root.push(import).unwrap();
+                }
+            } else if let Some(node) = self.parse_expr() {
+                in_body = true;
+                root.push(node).unwrap();
             } else {
-                break;
+                self.expected::<()>(&["expression"]);
+            }
+
+            if !self.recovering && !self.at_end() && !self.check(lexer::TokenType::Newline) {
+                self.expected::<()>(&[lexer::TokenType::Newline.description()]);
+            }
+            if self.recovering {
+                self.synchronise(start);
+                // There's no block for an `end` to close at the top level
+                self.matches(lexer::TokenType::End);
             }
         }
 
-        Some(ast)
+        if self.errors.is_empty() {
+            Ok(Ast { root, imports })
+        } else {
+            Err(self.errors)
+        }
+    }
+
+    fn parse_import_expr(&mut self) -> Option<Sexp<'tokens, Node>> {
+        if !self.matches(lexer::TokenType::Import) {
+            return None;
+        }
+
+        let offset = self.previous_token().offset();
+        let path = self
+            .parse_string_expr()
+            .or_else(|| self.expected(&["file path"]))?;
+        Some(s(Node::Import, vec![path], offset))
     }
 
     fn parse_expr(&mut self) -> Option<Sexp<'tokens, Node>> {
+        self.parse_documented_expr()
+            .or_else(|| self.parse_defmodule_expr())
+            .or_else(|| self.parse_deftype_expr())
+            .or_else(|| self.parse_def_expr())
+            .or_else(|| self.parse_defn_expr())
+            .or_else(|| self.parse_fn_expr())
+            .or_else(|| self.parse_condition_expr(lexer::TokenType::If, Node::If))
+            .or_else(|| self.parse_condition_expr(lexer::TokenType::Unless, Node::Unless))
+            .or_else(|| self.parse_try_expr())
+            .or_else(|| self.parse_call_expr())
+            .or_else(|| self.parse_terminal_expr())
+    }
+
+    fn parse_documented_expr(&mut self) -> Option<Sexp<'tokens, Node>> {
+        if !self.check(lexer::TokenType::DocComment) {
+            return None;
+        }
+
+        let offset = self.current_span().start;
+        let mut doc = s(Node::Doc, vec![], offset);
+        while self.matches(lexer::TokenType::DocComment) {
+            let comment = self.previous_token();
+            doc.push(lit(
+                Node::DocComment,
+                comment.text_content().unwrap(),
+                comment.literal().unwrap().clone(),
+                comment.offset(),
+            ))
+            .unwrap();
+        }
+
+        let definition = self
+            .parse_expr()
+            .or_else(|| self.expected(&["expression"]))?;
+        Some(s(Node::Documented, vec![doc, definition], offset))
+    }
+
+    fn parse_defmodule_expr(&mut self) -> Option<Sexp<'tokens, Node>> {
+        if !self.matches(lexer::TokenType::Defmodule) {
+            return None;
+        }
+
+        let offset = self.previous_token().offset();
+        let name = self
+            .parse_constant_expr()
+            .or_else(|| self.expected(&["module name"]))?;
+        let body = self
+            .parse_multiline_body_expr()
+            .or_else(|| self.expected(&[lexer::TokenType::Do.description()]))?;
+        Some(s(Node::Defmodule, vec![name, body], offset))
+    }
+
+    // The supertype and fields are optional, and stand in as `nil` and an empty array when left
+    // out, so a type definition always has the same shape
+    fn parse_deftype_expr(&mut self) -> Option<Sexp<'tokens, Node>> {
+        if !self.matches(lexer::TokenType::Deftype) {
+            return None;
+        }
+
+        let offset = self.previous_token().offset();
+        let name = self
+            .parse_constant_expr()
+            .or_else(|| self.expected(&["type name"]))?;
+        let supertype = self.parse_supertype_expr();
+        let fields = self
+            .parse_array_expr()
+            .unwrap_or_else(|| s(Node::Array, vec![], self.previous_token().span().end));
+        Some(s(Node::Deftype, vec![name, supertype, fields], offset))
+    }
+
+    // The type a `deftype` inherits from, or nothing if it doesn't name one
+    fn parse_supertype_expr(&mut self) -> Sexp<'tokens, Node> {
+        let supertype: Vec<_> = self
+            .parse_module_scoped_constant_expr()
+            .into_iter()
+            .collect();
+        let offset = self.list_offset(&supertype);
+        s(Node::Supertype, supertype, offset)
+    }
+
+    fn parse_def_expr(&mut self) -> Option<Sexp<'tokens, Node>> {
+        if !self.matches(lexer::TokenType::Def) {
+            return None;
+        }
+
+        let offset = self.previous_token().offset();
+        let ident = self
+            .parse_identifier_expr()
+            .or_else(|| self.expected(&["identifier"]))?;
+        let value = self
+            .parse_terminal_expr()
+            .or_else(|| self.expected(&["value"]))?;
+        Some(s(Node::Def, vec![ident, value], offset))
+    }
+
+    fn parse_defn_expr(&mut self) -> Option<Sexp<'tokens, Node>> {
+        if !self.matches(lexer::TokenType::Defn) {
+            return None;
+        }
+
+        let offset = self.previous_token().offset();
+        let ident = self
+            .parse_identifier_expr()
+            .or_else(|| self.expected(&["function name"]))?;
+        let params = self.parse_params_expr();
+        let body = self.parse_fn_body_expr()?;
+        Some(s(Node::Defn, vec![ident, params, body], offset))
+    }
+
+    fn parse_fn_expr(&mut self) -> Option<Sexp<'tokens, Node>> {
+        if !self.matches(lexer::TokenType::Fn) {
+            return None;
+        }
+
+        let offset = self.previous_token().offset();
+        let params = self.parse_params_expr();
+        let body = self.parse_fn_body_expr()?;
+        Some(s(Node::Fn, vec![params, body], offset))
+    }
+
+    fn parse_params_expr(&mut self) -> Sexp<'tokens, Node> {
+        let mut params = vec![];
+        while let Some(param) = self.parse_identifier_expr() {
+            params.push(param);
+        }
+        let offset = self.list_offset(&params);
+        s(Node::Params, params, offset)
+    }
+
+    fn parse_fn_body_expr(&mut self) -> Option<Sexp<'tokens, Node>> {
+        self.parse_guarded_body_expr()
+            .or_else(|| self.parse_single_line_body_expr())
+            .or_else(|| self.parse_multiline_body_expr())
+            .or_else(|| {
+                self.expected(&[
+                    lexer::TokenType::Arrow.description(),
+                    lexer::TokenType::Do.description(),
+                    lexer::TokenType::When.description(),
+                ])
+            })
+    }
+
+    // A function body made of `when condition` guards, each on its own line and optionally
+    // followed by an `else`, closed by `end`. The compiler checks there is at least one `when`.
+    fn parse_guarded_body_expr(&mut self) -> Option<Sexp<'tokens, Node>> {
+        if !self.check_after_newlines(lexer::TokenType::When)
+            && !self.check_after_newlines(lexer::TokenType::Else)
+        {
+            return None;
+        }
+
+        self.skip(lexer::TokenType::Newline);
+        let mut guards = s(Node::Guards, vec![], self.current_span().start);
+        loop {
+            self.skip(lexer::TokenType::Newline);
+            if self.matches(lexer::TokenType::When) {
+                let offset = self.previous_token().offset();
+                let condition = self.parse_condition()?;
+                let body = self.parse_when_body_expr()?;
+                guards
+                    .push(s(Node::When, vec![condition, body], offset))
+                    .unwrap();
+            } else if self.matches(lexer::TokenType::Else) {
+                let offset = self.previous_token().offset();
+                let body = self.parse_when_body_expr()?;
+                guards.push(s(Node::GuardElse, vec![body], offset)).unwrap();
+            } else {
+                break;
+            }
+        }
+
+        self.consume(&[
+            lexer::TokenType::When,
+            lexer::TokenType::Else,
+            lexer::TokenType::End,
+        ])?;
+        guards.extend_to(self.previous_token().span().end);
+        Some(guards)
+    }
+
+    fn parse_when_body_expr(&mut self) -> Option<Sexp<'tokens, Node>> {
+        self.parse_single_line_body_expr()
+            .or_else(|| self.parse_multiline_body_expr())
+            .or_else(|| {
+                self.expected(&[
+                    lexer::TokenType::Arrow.description(),
+                    lexer::TokenType::Do.description(),
+                ])
+            })
+    }
+
+    fn parse_single_line_body_expr(&mut self) -> Option<Sexp<'tokens, Node>> {
+        if !self.matches(lexer::TokenType::Arrow) {
+            return None;
+        }
+
+        let offset = self.previous_token().offset();
+        let expr = self
+            .parse_expr()
+            .or_else(|| self.expected(&["expression"]))?;
+        Some(s(Node::Block, vec![expr], offset))
+    }
+
+    fn parse_multiline_body_expr(&mut self) -> Option<Sexp<'tokens, Node>> {
+        if !self.matches(lexer::TokenType::Do) {
+            return None;
+        }
+
+        let mut block = self.parse_block(&[lexer::TokenType::End])?;
+        self.matches(lexer::TokenType::End);
+        block.extend_to(self.previous_token().span().end);
+        Some(block)
+    }
+
+    // `if` and `unless` take a condition followed by either a single-line body, or a `do ... end`
+    // block which may be split in two by `else`. The else body is left out when there isn't one.
+    fn parse_condition_expr(
+        &mut self,
+        keyword: lexer::TokenType,
+        node: Node,
+    ) -> Option<Sexp<'tokens, Node>> {
+        if !self.matches(keyword) {
+            return None;
+        }
+
+        let offset = self.previous_token().offset();
+        let condition = self.parse_condition()?;
+        if let Some(body) = self.parse_single_line_body_expr() {
+            return Some(s(node, vec![condition, body], offset));
+        }
+
+        self.consume(&[lexer::TokenType::Arrow, lexer::TokenType::Do])?;
+        let mut body = self.parse_block(&[lexer::TokenType::Else, lexer::TokenType::End])?;
+        if self.matches(lexer::TokenType::End) {
+            body.extend_to(self.previous_token().span().end);
+            return Some(s(node, vec![condition, body], offset));
+        }
+
+        self.matches(lexer::TokenType::Else);
+        let mut else_body = self.parse_block(&[lexer::TokenType::End])?;
+        self.matches(lexer::TokenType::End);
+        else_body.extend_to(self.previous_token().span().end);
+        Some(s(node, vec![condition, body, else_body], offset))
+    }
+
+    // A `do` block followed by one or more `rescue ErrorType handler` clauses and closed by `end`.
+    // The handler is called with the error when it's an instance of the clause's type.
+    fn parse_try_expr(&mut self) -> Option<Sexp<'tokens, Node>> {
+        if !self.matches(lexer::TokenType::Try) {
+            return None;
+        }
+
+        let offset = self.previous_token().offset();
+        self.consume(&[lexer::TokenType::Do])?;
+        let body = self.parse_block(&[lexer::TokenType::Rescue, lexer::TokenType::End])?;
+        let mut node = s(Node::Try, vec![body], offset);
+        while self.matches(lexer::TokenType::Rescue) {
+            let offset = self.previous_token().offset();
+            let type_ = self
+                .parse_module_scoped_constant_expr()
+                .or_else(|| self.expected(&["error type"]))?;
+            let handler = self.parse_expr().or_else(|| self.expected(&["handler"]))?;
+            node.push(s(Node::Rescue, vec![type_, handler], offset))
+                .unwrap();
+            self.skip(lexer::TokenType::Newline);
+        }
+
+        self.consume(&[lexer::TokenType::Rescue, lexer::TokenType::End])?;
+        node.extend_to(self.previous_token().span().end);
+        if node.children().len() == 1 {
+            self.invalid(node.span(), "try must have at least one rescue".to_owned());
+        }
+        Some(node)
+    }
+
+    // The condition of an `if`, `unless` or `when`
+    fn parse_condition(&mut self) -> Option<Sexp<'tokens, Node>> {
         self.parse_call_expr()
             .or_else(|| self.parse_terminal_expr())
+            .or_else(|| self.expected(&["condition"]))
+    }
+
+    // Parses the newline separated expressions of a block opened by the previous token, up to
+    // (but not including) one of the `closing` tokens. An expression with a syntax error is
+    // skipped, so the rest of the block can still be parsed.
+    fn parse_block(&mut self, closing: &[lexer::TokenType]) -> Option<Sexp<'tokens, Node>> {
+        let is_closed = |parser: &Self| closing.iter().any(|&token| parser.check(token));
+
+        let mut block = s(Node::Block, vec![], self.previous_token().offset());
+        loop {
+            self.skip(lexer::TokenType::Newline);
+            if self.at_end() || is_closed(self) {
+                break;
+            }
+
+            let start = self.position;
+            match self.parse_expr() {
+                Some(expr) => {
+                    block.push(expr).unwrap();
+                    if !self.check(lexer::TokenType::Newline) && !is_closed(self) {
+                        let mut expected = vec![lexer::TokenType::Newline];
+                        expected.extend(closing);
+                        self.consume(&expected);
+                    }
+                }
+                None => {
+                    self.expected::<()>(&["expression"]);
+                }
+            }
+            if self.recovering {
+                self.synchronise(start);
+            }
+        }
+
+        if is_closed(self) {
+            Some(block)
+        } else {
+            let expected: Vec<_> = closing.iter().map(|token| token.description()).collect();
+            self.expected(&expected)
+        }
     }
 
     fn parse_call_expr(&mut self) -> Option<Sexp<'tokens, Node>> {
+        self.parse_identifier_call_expr()
+            .or_else(|| self.parse_symbol_call_expr())
+            .or_else(|| self.parse_type_constructor_call_expr())
+    }
+
+    fn parse_identifier_call_expr(&mut self) -> Option<Sexp<'tokens, Node>> {
+        let ident = self.parse_module_scoped_identifier_expr()?;
+        let offset = ident.offset();
+        let args = self.parse_args_expr();
+        Some(s(Node::Call, vec![ident, args], offset))
+    }
+
+    // `:name user`, which looks a field or key up in what follows the symbol. A symbol followed by
+    // anything else is left to be parsed as a terminal.
+    fn parse_symbol_call_expr(&mut self) -> Option<Sexp<'tokens, Node>> {
+        let start = self.position;
+        let symbol = self.parse_symbol_expr()?;
+        let callee = self
+            .parse_module_scoped_identifier_expr()
+            .or_else(|| self.parse_hashmap_expr())
+            .or_else(|| self.parse_parenthesized_expr());
+        match callee {
+            Some(callee) => {
+                let offset = symbol.offset();
+                Some(s(Node::SymbolCall, vec![symbol, callee], offset))
+            }
+            None => {
+                self.position = start;
+                None
+            }
+        }
+    }
+
+    // `User "name" "email"` or `User {:name "name"}`, which builds a new instance of the type
+    fn parse_type_constructor_call_expr(&mut self) -> Option<Sexp<'tokens, Node>> {
+        let constant = self.parse_module_scoped_constant_expr()?;
+        let offset = constant.offset();
+        let args = self.parse_args_expr();
+        Some(s(Node::Constructor, vec![constant, args], offset))
+    }
+
+    fn parse_args_expr(&mut self) -> Sexp<'tokens, Node> {
+        let mut args = vec![];
+        while let Some(arg) = self.parse_terminal_expr() {
+            args.push(arg);
+        }
+        let offset = self.list_offset(&args);
+        s(Node::Args, args, offset)
+    }
+
+    // Where a (possibly empty) list of parameters or arguments starts, so an empty list is placed
+    // straight after the token preceding it
+    fn list_offset(&self, list: &[Sexp<'tokens, Node>]) -> usize {
+        list.first()
+            .map_or_else(|| self.previous_token().span().end, Sexp::offset)
+    }
+
+    // `Foo.bar`, a name defined in module `Foo`, or a plain identifier
+    fn parse_module_scoped_identifier_expr(&mut self) -> Option<Sexp<'tokens, Node>> {
+        self.parse_module_scoped_expr(
+            lexer::TokenType::Dot,
+            lexer::TokenType::Identifier,
+            Node::ModuleScopedIdentifier,
+        )
+        .or_else(|| self.parse_identifier_expr())
+    }
+
+    // `Foo::Bar`, a type or module defined in module `Foo`, or a plain constant
+    fn parse_module_scoped_constant_expr(&mut self) -> Option<Sexp<'tokens, Node>> {
+        self.parse_module_scoped_expr(
+            lexer::TokenType::DoubleColon,
+            lexer::TokenType::Constant,
+            Node::ModuleScopedConstant,
+        )
+        .or_else(|| self.parse_constant_expr())
+    }
+
+    fn parse_module_scoped_expr(
+        &mut self,
+        separator: lexer::TokenType,
+        name: lexer::TokenType,
+        node: Node,
+    ) -> Option<Sexp<'tokens, Node>> {
+        if !self.check(lexer::TokenType::Constant)
+            || !self.check_ahead(1, separator)
+            || !self.check_ahead(2, name)
+        {
+            return None;
+        }
+
+        let module = self.parse_constant_expr()?;
+        self.advance(1);
+        let name = self
+            .parse_identifier_expr()
+            .or_else(|| self.parse_constant_expr())?;
+        let offset = module.offset();
+        Some(s(node, vec![module, name], offset))
+    }
+
+    fn parse_identifier_expr(&mut self) -> Option<Sexp<'tokens, Node>> {
         if self.matches(lexer::TokenType::Identifier) {
             let ident = self.previous_token();
-            let ident = t(
+            return Some(t(
                 Node::Identifier,
                 ident.text_content().unwrap(),
                 ident.offset(),
-            );
-            let offset = ident.offset();
-            let args = self.parse_args_expr()?;
-            let children = vec![ident, args];
-            let result = Some(s(Node::Call, children, offset));
-            return result;
+            ));
         }
         None
     }
 
-    fn parse_args_expr(&mut self) -> Option<Sexp<'tokens, Node>> {
-        let mut node = s(Node::Args, vec![], self.current_token().offset());
-        while !self.at_end() {
-            if let Some(arg) = self.parse_terminal_expr() {
-                node.push(arg).unwrap();
-            } else {
-                break;
-            }
+    fn parse_constant_expr(&mut self) -> Option<Sexp<'tokens, Node>> {
+        if self.matches(lexer::TokenType::Constant) {
+            let constant = self.previous_token();
+            return Some(t(
+                Node::Constant,
+                constant.text_content().unwrap(),
+                constant.offset(),
+            ));
         }
-        return Some(node);
+        None
     }
 
     fn parse_terminal_expr(&mut self) -> Option<Sexp<'tokens, Node>> {
-        self.parse_integer_expr()
+        self.parse_module_scoped_identifier_expr()
+            .or_else(|| self.parse_keyword_expr(lexer::TokenType::True, Node::True))
+            .or_else(|| self.parse_keyword_expr(lexer::TokenType::False, Node::False))
+            .or_else(|| self.parse_keyword_expr(lexer::TokenType::Nil, Node::Nil))
+            .or_else(|| self.parse_integer_expr())
+            .or_else(|| self.parse_float_expr())
+            .or_else(|| self.parse_parenthesized_expr())
+            .or_else(|| self.parse_array_expr())
+            .or_else(|| self.parse_hashmap_expr())
+            .or_else(|| self.parse_string_expr())
+            .or_else(|| self.parse_interpolation_expr())
+            .or_else(|| self.parse_symbol_expr())
+            .or_else(|| self.parse_ref_expr())
+    }
+
+    // `&foo`, `&Foo.bar` or `&Foo`, which refer to a function or type without calling it
+    fn parse_ref_expr(&mut self) -> Option<Sexp<'tokens, Node>> {
+        if !self.matches(lexer::TokenType::Ref) {
+            return None;
+        }
+
+        let offset = self.previous_token().offset();
+        let target = self
+            .parse_module_scoped_identifier_expr()
+            .or_else(|| self.parse_module_scoped_constant_expr());
+        let target = target.or_else(|| self.expected(&["name to reference"]))?;
+        Some(s(Node::Ref, vec![target], offset))
+    }
+
+    // `true`, `false` and `nil`, which have no value besides their type
+    fn parse_keyword_expr(
+        &mut self,
+        keyword: lexer::TokenType,
+        node: Node,
+    ) -> Option<Sexp<'tokens, Node>> {
+        if self.matches(keyword) {
+            let token = self.previous_token();
+            return Some(t(node, token.text_content().unwrap(), token.offset()));
+        }
+        None
+    }
+
+    // Parentheses only group, so they leave no node of their own in the tree
+    fn parse_parenthesized_expr(&mut self) -> Option<Sexp<'tokens, Node>> {
+        if !self.matches(lexer::TokenType::LeftParen) {
+            return None;
+        }
+
+        let expr = self
+            .parse_expr()
+            .or_else(|| self.expected(&["expression"]))?;
+        self.consume(&[lexer::TokenType::RightParen])?;
+        Some(expr)
+    }
+
+    fn parse_array_expr(&mut self) -> Option<Sexp<'tokens, Node>> {
+        self.parse_collection_expr(
+            lexer::TokenType::LeftBracket,
+            lexer::TokenType::RightBracket,
+            Node::Array,
+        )
+    }
+
+    // A hashmap's children alternate between keys and their values
+    fn parse_hashmap_expr(&mut self) -> Option<Sexp<'tokens, Node>> {
+        self.parse_collection_expr(
+            lexer::TokenType::LeftBrace,
+            lexer::TokenType::RightBrace,
+            Node::Hashmap,
+        )
+    }
+
+    fn parse_collection_expr(
+        &mut self,
+        opening: lexer::TokenType,
+        closing: lexer::TokenType,
+        node: Node,
+    ) -> Option<Sexp<'tokens, Node>> {
+        if !self.matches(opening) {
+            return None;
+        }
+
+        // Elements may be spread over several lines
+        let mut collection = s(node, vec![], self.previous_token().offset());
+        self.skip(lexer::TokenType::Newline);
+        while let Some(element) = self.parse_terminal_expr() {
+            collection.push(element).unwrap();
+            self.skip(lexer::TokenType::Newline);
+        }
+
+        self.consume(&[closing])?;
+        collection.extend_to(self.previous_token().span().end);
+        let count = collection.children().len();
+        if matches!(node, Node::Hashmap) && count % 2 != 0 {
+            self.invalid(
+                collection.span(),
+                format!(
+                    "Hashmap literal has an odd number of elements ({}), so its last key has no value",
+                    count
+                ),
+            );
+        }
+        Some(collection)
     }
 
     fn parse_integer_expr(&mut self) -> Option<Sexp<'tokens, Node>> {
         if self.matches(lexer::TokenType::Integer) {
             let integer = self.previous_token();
-            return Some(t(
+            return Some(lit(
                 Node::Integer,
                 integer.text_content().unwrap(),
+                integer.literal().unwrap().clone(),
                 integer.offset(),
             ));
         }
         None
     }
 
+    fn parse_float_expr(&mut self) -> Option<Sexp<'tokens, Node>> {
+        if self.matches(lexer::TokenType::Float) {
+            let float = self.previous_token();
+            return Some(lit(
+                Node::Float,
+                float.text_content().unwrap(),
+                float.literal().unwrap().clone(),
+                float.offset(),
+            ));
+        }
+        None
+    }
+
+    fn parse_string_expr(&mut self) -> Option<Sexp<'tokens, Node>> {
+        if self.matches(lexer::TokenType::String) {
+            let string = self.previous_token();
+            return Some(lit(
+                Node::String,
+                string.text_content().unwrap(),
+                string.literal().unwrap().clone(),
+                string.offset(),
+            ));
+        }
+        None
+    }
+
+    fn parse_symbol_expr(&mut self) -> Option<Sexp<'tokens, Node>> {
+        if self.matches(lexer::TokenType::Symbol) {
+            let symbol = self.previous_token();
+            return Some(lit(
+                Node::Symbol,
+                symbol.text_content().unwrap(),
+                symbol.literal().unwrap().clone(),
+                symbol.offset(),
+            ));
+        }
+        None
+    }
+
+    // An interpolated string's children alternate between its string segments and the
+    // interpolated expressions, starting and ending with a (possibly empty) segment
+    fn parse_interpolation_expr(&mut self) -> Option<Sexp<'tokens, Node>> {
+        if !self.matches(lexer::TokenType::InterpolationStart) {
+            return None;
+        }
+
+        let offset = self.previous_token().offset();
+        let mut node = s(Node::Interpolation, vec![], offset);
+        loop {
+            let segment = self.previous_token();
+            node.push(lit(
+                Node::String,
+                segment.text_content().unwrap(),
+                segment.literal().unwrap().clone(),
+                segment.offset(),
+            ))
+            .unwrap();
+            if segment.type_() == lexer::TokenType::InterpolationEnd {
+                return Some(node);
+            }
+
+            let expr = self
+                .parse_expr()
+                .or_else(|| self.expected(&["expression"]))?;
+            node.push(expr).unwrap();
+            self.consume(&[
+                lexer::TokenType::InterpolationMiddle,
+                lexer::TokenType::InterpolationEnd,
+            ])?;
+        }
+    }
+
+    // Records that one of `expected` should have come next, unless the current expression already
+    // has an error. Always gives nothing, to end the expression's parse.
+    fn expected<U>(&mut self, expected: &[&'static str]) -> Option<U> {
+        if !self.recovering {
+            let found = match self.current_token() {
+                None => lexer::TokenType::Eof.description().to_owned(),
+                Some(token) if token.type_() == lexer::TokenType::Newline => {
+                    lexer::TokenType::Newline.description().to_owned()
+                }
+                Some(token) => format!("`{}`", token.text_content().unwrap_or_default()),
+            };
+            self.errors.push(ParseError {
+                span: self.current_span(),
+                kind: ParseErrorKind::Unexpected {
+                    expected: expected.to_vec(),
+                    found,
+                },
+            });
+            self.recovering = true;
+        }
+        None
+    }
+
+    // Records an error which doesn't stop the rest of the expression being parsed
+    fn invalid(&mut self, span: Span, message: String) {
+        self.errors.push(ParseError {
+            span,
+            kind: ParseErrorKind::Invalid(message),
+        });
+    }
+
+    // Consumes one of the given tokens, or records that it was expected
+    fn consume(&mut self, token_types: &[lexer::TokenType]) -> Option<()> {
+        if token_types
+            .iter()
+            .any(|&token_type| self.matches(token_type))
+        {
+            return Some(());
+        }
+        let expected: Vec<_> = token_types
+            .iter()
+            .map(|token| token.description())
+            .collect();
+        self.expected(&expected)
+    }
+
+    // Skips the rest of the expression starting at `start` which has a syntax error, up to the
+    // newline or `end` after it, along with any `do ... end` blocks it opened (including those
+    // opened before the error). Parsing can't carry on at the end of the file, so the error is
+    // left to end any unfinished expressions there.
+    fn synchronise(&mut self, start: usize) {
+        let opened = self.tokens[start..self.position]
+            .iter()
+            .map(|token| match token.type_() {
+                lexer::TokenType::Do => 1,
+                lexer::TokenType::End => -1,
+                _ => 0,
+            })
+            .sum::<i32>();
+        let mut depth = opened.max(0);
+        while let Some(token) = self.current_token() {
+            match token.type_() {
+                lexer::TokenType::Newline if depth == 0 => break,
+                lexer::TokenType::End if depth == 0 => break,
+                lexer::TokenType::Do => depth += 1,
+                lexer::TokenType::End => depth -= 1,
+                _ => {}
+            }
+            self.advance(1);
+        }
+        self.recovering = self.at_end();
+    }
+
+    fn check(&self, token_type: lexer::TokenType) -> bool {
+        self.current_token()
+            .is_some_and(|token| token.type_() == token_type)
+    }
+
     fn matches(&mut self, token_type: lexer::TokenType) -> bool {
-        if self.current_token().type_() == token_type {
+        if self.check(token_type) {
             self.advance(1);
             true
         } else {
@@ -106,6 +909,25 @@ impl<'file: 'tokens, 'tokens, T: file::File<'file>> Parser<'file, 'tokens, T> {
         }
     }
 
+    // Whether the token `n` places after the current one is of the given type
+    fn check_ahead(&self, n: usize, token_type: lexer::TokenType) -> bool {
+        self.tokens
+            .get(self.position + n)
+            .is_some_and(|token| token.type_() == token_type)
+    }
+
+    // Whether the next token other than a newline is of the given type
+    fn check_after_newlines(&self, token_type: lexer::TokenType) -> bool {
+        self.tokens[self.position..]
+            .iter()
+            .find(|token| token.type_() != lexer::TokenType::Newline)
+            .is_some_and(|token| token.type_() == token_type)
+    }
+
+    fn skip(&mut self, token_type: lexer::TokenType) {
+        while self.matches(token_type) {}
+    }
+
     fn at_end(&self) -> bool {
         self.position == self.tokens.len()
     }
@@ -114,11 +936,496 @@ impl<'file: 'tokens, 'tokens, T: file::File<'file>> Parser<'file, 'tokens, T> {
         &self.tokens[self.position - 1]
     }
 
-    fn current_token(&self) -> &'tokens Sexp<'tokens, lexer::TokenType> {
-        &self.tokens[self.position]
+    fn current_token(&self) -> Option<&'tokens Sexp<'tokens, lexer::TokenType>> {
+        self.tokens.get(self.position)
+    }
+
+    // The current token's span, or an empty span at the end of the file when there are no tokens
+    // left
+    fn current_span(&self) -> Span {
+        self.current_token().map_or_else(
+            || {
+                let end = self.file.contents().len();
+                Span::new(end, end)
+            },
+            Sexp::span,
+        )
     }
 
     fn advance(&mut self, n: usize) {
         self.position += n;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::file::ScriptFile;
+    use crate::compiler::sexp::Span;
+
+    // Renders a tree compactly, with terminals as their source text, e.g. `Call(+ Args(1 2))`
+    fn shape(node: &Sexp<Node>) -> String {
+        match node.text_content() {
+            Some(text) => text.to_owned(),
+            None => {
+                let children: Vec<_> = node.children().iter().map(shape).collect();
+                format!("{:?}({})", node.type_(), children.join(" "))
+            }
+        }
+    }
+
+    fn parse_shape(source: &str) -> String {
+        let file = ScriptFile::new(source);
+        let tokens = lexer::tokenise(&file, true).unwrap();
+        shape(&parse(&file, &tokens).unwrap().root)
+    }
+
+    // The offset and message of each error from parsing the source
+    fn parse_errors(source: &str) -> Vec<(usize, String)> {
+        let file = ScriptFile::new(source);
+        let tokens = lexer::tokenise(&file, true).unwrap();
+        parse(&file, &tokens)
+            .err()
+            .unwrap_or_default()
+            .iter()
+            .map(|error| (error.span.start, error.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_definitions() {
+        assert_eq!(parse_shape("def x 1"), "Block(Def(x 1))");
+        assert_eq!(parse_shape("def y x"), "Block(Def(y x))");
+    }
+
+    #[test]
+    fn parses_collections_and_parenthesized_arguments() {
+        assert_eq!(
+            parse_shape("fib (- n 1)"),
+            "Block(Call(fib Args(Call(- Args(n 1)))))"
+        );
+        assert_eq!(
+            parse_shape("def nums [0 1, 2 []]"),
+            "Block(Def(nums Array(0 1 2 Array())))"
+        );
+        assert_eq!(
+            parse_shape("def a {:foo \"bar\", :baz [true false nil]}"),
+            "Block(Def(a Hashmap(:foo \"bar\" :baz Array(true false nil))))"
+        );
+        assert_eq!(
+            parse_shape("map (fn a -> * a a) [1 2]"),
+            "Block(Call(map Args(Fn(Params(a) Block(Call(* Args(a a)))) Array(1 2))))"
+        );
+        assert_eq!(
+            parse_shape("def a [\n  1\n  2\n]\n{\n  :b [3\n    4]\n\n}"),
+            "Block(Def(a Array(1 2)) Hashmap(:b Array(3 4)))"
+        );
+    }
+
+    #[test]
+    fn rejects_hashmaps_with_a_key_missing_its_value() {
+        assert_eq!(
+            parse_errors("def a {:foo \"bar\" :baz}\nb"),
+            vec![(
+                6,
+                "Hashmap literal has an odd number of elements (3), so its last key has no value"
+                    .to_owned()
+            )]
+        );
+    }
+
+    #[test]
+    fn collections_span_their_brackets() {
+        let file = ScriptFile::new("f [1 2] {}");
+        let tokens = lexer::tokenise(&file, true).unwrap();
+        let ast = parse(&file, &tokens).unwrap().root;
+        let args = &ast.children()[0].children()[1];
+        assert_eq!(args.children()[0].span(), Span::new(2, 7));
+        assert_eq!(args.children()[1].span(), Span::new(8, 10));
+    }
+
+    #[test]
+    fn parses_type_definitions() {
+        assert_eq!(
+            parse_shape("deftype Empty"),
+            "Block(Deftype(Empty Supertype() Array()))"
+        );
+        assert_eq!(
+            parse_shape("deftype User Base [:name :email]"),
+            "Block(Deftype(User Supertype(Base) Array(:name :email)))"
+        );
+        assert_eq!(
+            parse_shape("deftype MyError Error"),
+            "Block(Deftype(MyError Supertype(Error) Array()))"
+        );
+        assert_eq!(
+            parse_shape("deftype NotFound Http::Error"),
+            "Block(Deftype(NotFound Supertype(ModuleScopedConstant(Http Error)) Array()))"
+        );
+    }
+
+    #[test]
+    fn parses_type_constructor_calls() {
+        assert_eq!(
+            parse_shape("User \"Test\" \"a@b\""),
+            "Block(Constructor(User Args(\"Test\" \"a@b\")))"
+        );
+        assert_eq!(
+            parse_shape("def u (User {:name \"Test\"})"),
+            "Block(Def(u Constructor(User Args(Hashmap(:name \"Test\")))))"
+        );
+        assert_eq!(parse_shape("Empty"), "Block(Constructor(Empty Args()))");
+    }
+
+    #[test]
+    fn parses_modules() {
+        let source = "defmodule Foo do\n  def test_string \"String\"\n  deftype Bar\nend";
+        assert_eq!(
+            parse_shape(source),
+            "Block(Defmodule(Foo Block(Def(test_string \"String\") Deftype(Bar Supertype() Array()))))"
+        );
+        assert_eq!(
+            parse_shape("== 1 Foo.test_fn"),
+            "Block(Call(== Args(1 ModuleScopedIdentifier(Foo test_fn))))"
+        );
+        assert_eq!(
+            parse_shape("Foo.add 1 2"),
+            "Block(Call(ModuleScopedIdentifier(Foo add) Args(1 2)))"
+        );
+        assert_eq!(
+            parse_shape("def bar_instance (Foo::Bar)"),
+            "Block(Def(bar_instance Constructor(ModuleScopedConstant(Foo Bar) Args())))"
+        );
+    }
+
+    #[test]
+    fn module_scoped_names_span_both_parts() {
+        let file = ScriptFile::new("f Foo.bar\nFoo::Bar");
+        let tokens = lexer::tokenise(&file, true).unwrap();
+        let ast = parse(&file, &tokens).unwrap().root;
+        let identifier = &ast.children()[0].children()[1].children()[0];
+        assert_eq!(identifier.span(), Span::new(2, 9));
+        let constant = &ast.children()[1].children()[0];
+        assert_eq!(constant.span(), Span::new(10, 18));
+    }
+
+    #[test]
+    fn collects_imports() {
+        let file = ScriptFile::new("\nimport \"test\"\nimport \"lib/other.em\"\n\nfoo 1");
+        let tokens = lexer::tokenise(&file, true).unwrap();
+        let ast = parse(&file, &tokens).unwrap();
+        assert_eq!(
+            ast.imports,
+            vec![
+                Import {
+                    path: "test".to_owned(),
+                    offset: 8,
+                },
+                Import {
+                    path: "lib/other.em".to_owned(),
+                    offset: 22,
+                },
+            ]
+        );
+        assert_eq!(
+            shape(&ast.root),
+            "Block(Import(\"test\") Import(\"lib/other.em\") Call(foo Args(1)))"
+        );
+    }
+
+    #[test]
+    fn rejects_imports_after_other_expressions() {
+        assert_eq!(
+            parse_errors("import \"a\"\nfoo\nimport \"b\""),
+            vec![(15, "import must appear before other expressions".to_owned())]
+        );
+    }
+
+    #[test]
+    fn parses_empty_files() {
+        assert_eq!(parse_shape(""), "Block()");
+        assert_eq!(parse_shape("\n\n"), "Block()");
+    }
+
+    #[test]
+    fn reports_expected_and_found_tokens() {
+        assert_eq!(
+            parse_errors("[1 2"),
+            vec![(4, "Expected `]`, got end of input".to_owned())]
+        );
+        assert_eq!(
+            parse_errors("foo 1 )"),
+            vec![(6, "Expected newline, got `)`".to_owned())]
+        );
+        assert_eq!(
+            parse_errors("defn f x 1"),
+            vec![(9, "Expected `->`, `do` or `when`, got `1`".to_owned())]
+        );
+        assert_eq!(
+            parse_errors("defn f do\n  x\n"),
+            vec![(14, "Expected `end`, got end of input".to_owned())]
+        );
+    }
+
+    #[test]
+    fn recovers_after_errors_to_report_more() {
+        assert_eq!(
+            parse_errors("def 1 2\ndef y\nprint y"),
+            vec![
+                (4, "Expected identifier, got `1`".to_owned()),
+                (13, "Expected value, got newline".to_owned())
+            ]
+        );
+        // The rest of an expression is skipped along with any blocks it opens, and a block's
+        // expressions recover at the newline or `end` after them
+        assert_eq!(
+            parse_errors(
+                "if do\n  ) 1\nend\ndefn f do\n  def x )\n  if y do\n    ]\n  end\nend\n)"
+            ),
+            vec![
+                (3, "Expected condition, got `do`".to_owned()),
+                (34, "Expected value, got `)`".to_owned()),
+                (50, "Expected expression, got `]`".to_owned()),
+                (62, "Expected expression, got `)`".to_owned())
+            ]
+        );
+        assert_eq!(
+            parse_errors("try do\n  a\nrescue 1 x\nend\nb )"),
+            vec![
+                (18, "Expected error type, got `1`".to_owned()),
+                (28, "Expected newline, got `)`".to_owned())
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_symbol_calls() {
+        assert_eq!(parse_shape("(:name user)"), "Block(SymbolCall(:name user))");
+        assert_eq!(
+            parse_shape("== (:foo {:foo 1}) :bar"),
+            "Block(Call(== Args(SymbolCall(:foo Hashmap(:foo 1)) :bar)))"
+        );
+        assert_eq!(
+            parse_shape(":email (User \"a\" \"b\")"),
+            "Block(SymbolCall(:email Constructor(User Args(\"a\" \"b\"))))"
+        );
+        assert_eq!(parse_shape(":foo\n1"), "Block(:foo 1)");
+    }
+
+    #[test]
+    fn parses_refs() {
+        assert_eq!(
+            parse_shape("map &inc arr"),
+            "Block(Call(map Args(Ref(inc) arr)))"
+        );
+        assert_eq!(
+            parse_shape("type &Foo.test_fn"),
+            "Block(Call(type Args(Ref(ModuleScopedIdentifier(Foo test_fn)))))"
+        );
+        assert_eq!(
+            parse_shape("== &String &Foo::Bar"),
+            "Block(Call(== Args(Ref(String) Ref(ModuleScopedConstant(Foo Bar)))))"
+        );
+        assert_eq!(
+            parse_errors("def x &1"),
+            vec![(7, "Expected name to reference, got `1`".to_owned())]
+        );
+    }
+
+    #[test]
+    fn parses_try_and_rescue() {
+        let source = "try do\n  test_fn\n  print \".\"\nrescue Test::AssertionError (fn e -> \
+                      print \"F\")\nrescue Error &report\nend";
+        assert_eq!(
+            parse_shape(source),
+            "Block(Try(Block(Call(test_fn Args()) Call(print Args(\".\"))) \
+             Rescue(ModuleScopedConstant(Test AssertionError) \
+             Fn(Params(e) Block(Call(print Args(\"F\"))))) Rescue(Error Ref(report))))"
+        );
+        // The handler from samples/sandbox.em, a guarded function spanning several lines
+        let source = "try do\n  a\nrescue Error (fn e\n  when b -> 1\n  else -> 2\n  end)\nend";
+        assert_eq!(
+            parse_shape(source),
+            "Block(Try(Block(Call(a Args())) Rescue(Error Fn(Params(e) \
+             Guards(When(Call(b Args()) Block(1)) GuardElse(Block(2)))))))"
+        );
+
+        assert_eq!(
+            parse_errors("try do\n  a\nend"),
+            vec![(0, "try must have at least one rescue".to_owned())]
+        );
+    }
+
+    #[test]
+    fn parses_single_line_functions() {
+        assert_eq!(
+            parse_shape("defn inc n -> + n 1"),
+            "Block(Defn(inc Params(n) Block(Call(+ Args(n 1)))))"
+        );
+        assert_eq!(
+            parse_shape("fn a b -> * a b"),
+            "Block(Fn(Params(a b) Block(Call(* Args(a b)))))"
+        );
+        assert_eq!(
+            parse_shape("defn zero -> 0"),
+            "Block(Defn(zero Params() Block(0)))"
+        );
+    }
+
+    #[test]
+    fn parses_multiline_functions() {
+        let source = "defn greet name do\n  def greeting \"Hello\"\n\n  println greeting name\nend\ngreet \"x\"";
+        assert_eq!(
+            parse_shape(source),
+            "Block(Defn(greet Params(name) Block(Def(greeting \"Hello\") \
+             Call(println Args(greeting name)))) Call(greet Args(\"x\")))"
+        );
+        assert_eq!(parse_shape("fn do end"), "Block(Fn(Params() Block()))");
+        assert_eq!(
+            parse_shape("defn f do 1\n2 end"),
+            "Block(Defn(f Params() Block(1 2)))"
+        );
+    }
+
+    #[test]
+    fn parses_single_line_conditions() {
+        assert_eq!(
+            parse_shape("if == 1 1 -> true_thing"),
+            "Block(If(Call(== Args(1 1)) Block(Call(true_thing Args()))))"
+        );
+        assert_eq!(
+            parse_shape("unless x -> \"no\""),
+            "Block(Unless(Call(x Args()) Block(\"no\")))"
+        );
+    }
+
+    #[test]
+    fn parses_multiline_conditions() {
+        assert_eq!(
+            parse_shape("if <= n 1 do\n  1\nelse\n  + n 1\nend"),
+            "Block(If(Call(<= Args(n 1)) Block(1) Block(Call(+ Args(n 1)))))"
+        );
+        assert_eq!(
+            parse_shape("unless done do\n  a\n  b\nend"),
+            "Block(Unless(Call(done Args()) Block(Call(a Args()) Call(b Args()))))"
+        );
+        // The layout used in the language tests, with the body lined up under the condition
+        let source = "if == 1 1 do\n          1\n        else\n          2\n        end";
+        assert_eq!(
+            parse_shape(source),
+            "Block(If(Call(== Args(1 1)) Block(1) Block(2)))"
+        );
+        assert_eq!(
+            parse_shape("if a do else 2 end"),
+            "Block(If(Call(a Args()) Block() Block(2)))"
+        );
+    }
+
+    #[test]
+    fn condition_nodes_span_to_their_end() {
+        let file = ScriptFile::new("if a do\n  1\nelse\n  2\nend\nunless b do 3 end");
+        let tokens = lexer::tokenise(&file, true).unwrap();
+        let ast = parse(&file, &tokens).unwrap().root;
+        let (if_, unless) = (&ast.children()[0], &ast.children()[1]);
+        assert_eq!(if_.span(), Span::new(0, 24));
+        assert_eq!(if_.children()[1].span(), Span::new(5, 11));
+        assert_eq!(if_.children()[2].span(), Span::new(12, 24));
+        assert_eq!(unless.span(), Span::new(25, 42));
+    }
+
+    #[test]
+    fn parses_guarded_functions() {
+        let source = "defn fib n\n  when <= n 1 -> 1\n  else -> + n 1\nend";
+        assert_eq!(
+            parse_shape(source),
+            "Block(Defn(fib Params(n) Guards(When(Call(<= Args(n 1)) Block(1)) \
+             GuardElse(Block(Call(+ Args(n 1)))))))"
+        );
+        let source =
+            "defn even? x\n  when == 0 x -> true_value\n\n  when == 1 x do\n    a\n  end\nend";
+        assert_eq!(
+            parse_shape(source),
+            "Block(Defn(even? Params(x) Guards(When(Call(== Args(0 x)) \
+             Block(Call(true_value Args()))) When(Call(== Args(1 x)) Block(Call(a Args()))))))"
+        );
+    }
+
+    #[test]
+    fn guards_span_to_their_end() {
+        let file = ScriptFile::new("fn x\n  when x -> 1\nend");
+        let tokens = lexer::tokenise(&file, true).unwrap();
+        let ast = parse(&file, &tokens).unwrap().root;
+        let guards = &ast.children()[0].children()[1];
+        assert_eq!(guards.span(), Span::new(7, 22));
+        assert_eq!(guards.children()[0].span(), Span::new(7, 18));
+    }
+
+    #[test]
+    fn function_nodes_span_their_whole_definition() {
+        let file = ScriptFile::new("defn f a do\n  a\nend\n");
+        let tokens = lexer::tokenise(&file, true).unwrap();
+        let ast = parse(&file, &tokens).unwrap().root;
+        let defn = &ast.children()[0];
+        assert_eq!(defn.span(), Span::new(0, 19));
+        assert_eq!(defn.children()[1].span(), Span::new(7, 8));
+        assert_eq!(defn.children()[2].span(), Span::new(9, 19));
+    }
+
+    #[test]
+    fn empty_lists_sit_after_the_preceding_token() {
+        let file = ScriptFile::new("defn f -> g");
+        let tokens = lexer::tokenise(&file, true).unwrap();
+        let ast = parse(&file, &tokens).unwrap().root;
+        let defn = &ast.children()[0];
+        assert_eq!(defn.children()[1].span(), Span::new(6, 6));
+        let call = &defn.children()[2].children()[0];
+        assert_eq!(call.children()[1].span(), Span::new(11, 11));
+    }
+
+    #[test]
+    fn attaches_doc_comments_to_functions() {
+        assert_eq!(
+            parse_shape("# Adds one\ndefn inc n -> + n 1"),
+            "Block(Documented(Doc(# Adds one) Defn(inc Params(n) Block(Call(+ Args(n 1))))))"
+        );
+    }
+
+    #[test]
+    fn nodes_span_their_children() {
+        let file = ScriptFile::new("foo 1   22");
+        let tokens = lexer::tokenise(&file, true).unwrap();
+        let ast = parse(&file, &tokens).unwrap().root;
+        let call = &ast.children()[0];
+        assert_eq!(ast.span(), Span::new(0, 10));
+        assert_eq!(call.span(), Span::new(0, 10));
+        assert_eq!(call.children()[1].span(), Span::new(4, 10));
+    }
+
+    #[test]
+    fn parses_expressions_on_separate_lines() {
+        let file = ScriptFile::new("#!/usr/bin/env emerald\n\n+ 1 2\n\n\"a\"\n");
+        let tokens = lexer::tokenise(&file, true).unwrap();
+        let ast = parse(&file, &tokens).unwrap().root;
+        let types: Vec<_> = ast
+            .children()
+            .iter()
+            .map(|child| format!("{:?}", child.type_()))
+            .collect();
+        assert_eq!(types, vec!["Call", "String"]);
+    }
+
+    #[test]
+    fn parses_interpolated_strings() {
+        let file = ScriptFile::new(r#"foo "a #{b} c #{+ 1 2}""#);
+        let tokens = lexer::tokenise(&file, true).unwrap();
+        let ast = parse(&file, &tokens).unwrap().root;
+        let interpolation = &ast.children()[0].children()[1].children()[0];
+        let types: Vec<_> = interpolation
+            .children()
+            .iter()
+            .map(|child| format!("{:?}", child.type_()))
+            .collect();
+        assert_eq!(types, vec!["String", "Call", "String", "Call", "String"]);
+        assert_eq!(interpolation.span(), Span::new(4, 23));
+    }
+}
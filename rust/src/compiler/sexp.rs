@@ -6,31 +6,73 @@ pub enum Sexp<'a, T> {
     NonTerminal(NonTerminal<'a, T>),
 }
 
+/// The half-open byte range `start..end` of the source text a token or node was parsed from
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+}
+
+impl Debug for Span {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Integer(i64),
+    Float(f64),
+    String(String),
+    Symbol(String),
+}
+
 impl<'a, T: Debug + Copy> Sexp<'a, T> {
-    pub fn len(&'a self) -> usize {
+    pub fn text_content(&self) -> Option<&'a str> {
         match self {
-            Sexp::Terminal(terminal) => terminal.contents.len(),
-            Sexp::NonTerminal(non_terminal) => non_terminal
-                .contents
-                .iter()
-                .fold(0, |acc, sexp| acc + sexp.len()),
+            Sexp::Terminal(terminal) => Some(terminal.contents),
+            Sexp::NonTerminal(_) => None,
         }
     }
 
-    pub fn text_content(&self) -> Option<&str> {
+    pub fn children(&self) -> &[Sexp<'a, T>] {
         match self {
-            Sexp::Terminal(terminal) => Some(terminal.contents),
-            Sexp::NonTerminal(non_terminal) => None,
+            Sexp::Terminal(_) => &[],
+            Sexp::NonTerminal(non_terminal) => &non_terminal.contents,
         }
     }
 
-    pub fn offset(&self) -> usize {
+    /// Attaches the whitespace, commas and comments preceding a token, when tokenising losslessly
+    pub fn attach_trivia(&mut self, trivia: Vec<Sexp<'a, T>>) {
+        if let Sexp::Terminal(terminal) = self {
+            terminal.trivia = trivia;
+        }
+    }
+
+    pub fn literal(&self) -> Option<&Literal> {
+        match self {
+            Sexp::Terminal(terminal) => terminal.literal.as_ref(),
+            Sexp::NonTerminal(_) => None,
+        }
+    }
+
+    pub fn span(&self) -> Span {
         match self {
-            Sexp::Terminal(terminal) => terminal.offset,
-            Sexp::NonTerminal(non_terminal) => non_terminal.offset,
+            Sexp::Terminal(terminal) => terminal.span,
+            Sexp::NonTerminal(non_terminal) => non_terminal.span,
         }
     }
 
+    pub fn offset(&self) -> usize {
+        self.span().start
+    }
+
     pub fn type_(&self) -> T {
         match self {
             Sexp::Terminal(terminal) => terminal.type_,
@@ -38,10 +80,18 @@ impl<'a, T: Debug + Copy> Sexp<'a, T> {
         }
     }
 
+    /// Widens a node's span to cover closing tokens which aren't kept as children, e.g. `end`
+    pub fn extend_to(&mut self, end: usize) {
+        if let Sexp::NonTerminal(non_terminal) = self {
+            non_terminal.span.end = non_terminal.span.end.max(end);
+        }
+    }
+
     pub fn push(&mut self, value: Sexp<'a, T>) -> Result<(), ()> {
         match self {
             Sexp::Terminal(_) => Err(()),
             Sexp::NonTerminal(non_terminal) => {
+                non_terminal.span.end = non_terminal.span.end.max(value.span().end);
                 non_terminal.contents.push(value);
                 Ok(())
             }
@@ -61,7 +111,9 @@ impl<'a, T: Debug> Debug for Sexp<'a, T> {
 pub struct Terminal<'a, T> {
     pub type_: T,
     pub contents: &'a str,
-    pub offset: usize,
+    pub literal: Option<Literal>,
+    pub trivia: Vec<Sexp<'a, T>>,
+    pub span: Span,
 }
 
 impl<'a, T: Debug> Debug for Terminal<'a, T> {
@@ -70,16 +122,23 @@ impl<'a, T: Debug> Debug for Terminal<'a, T> {
         self.type_.fmt(f)?;
         f.write_str(", ")?;
         self.contents.fmt(f)?;
-        f.write_str(", offset: ")?;
-        self.offset.fmt(f)?;
+        if let Some(literal) = &self.literal {
+            write!(f, ", {:?}", literal)?;
+        }
+        if !self.trivia.is_empty() {
+            f.write_str(", trivia: ")?;
+            self.trivia.fmt(f)?;
+        }
+        f.write_str(", span: ")?;
+        self.span.fmt(f)?;
         f.write_str(")")
     }
 }
 
 pub struct NonTerminal<'a, T> {
     pub type_: T,
-    pub contents: Box<Vec<Sexp<'a, T>>>,
-    pub offset: usize,
+    pub contents: Vec<Sexp<'a, T>>,
+    pub span: Span,
 }
 
 impl<'a, T: Debug> Debug for NonTerminal<'a, T> {
@@ -88,24 +147,41 @@ impl<'a, T: Debug> Debug for NonTerminal<'a, T> {
         self.type_.fmt(f)?;
         f.write_str(", ")?;
         self.contents.fmt(f)?;
-        f.write_str(", offset: ")?;
-        self.offset.fmt(f)?;
+        f.write_str(", span: ")?;
+        self.span.fmt(f)?;
         f.write_str(")")
     }
 }
 
-pub fn s<T: Debug>(type_: T, contents: Vec<Sexp<T>>, offset: usize) -> Sexp<T> {
+pub fn s<T: Debug + Copy>(type_: T, contents: Vec<Sexp<'_, T>>, offset: usize) -> Sexp<'_, T> {
+    let end = contents
+        .iter()
+        .map(|sexp| sexp.span().end)
+        .fold(offset, usize::max);
     Sexp::NonTerminal(NonTerminal {
         type_,
-        contents: Box::new(contents),
-        offset,
+        contents,
+        span: Span::new(offset, end),
+    })
+}
+
+pub fn t<T: Debug>(type_: T, contents: &str, offset: usize) -> Sexp<'_, T> {
+    Sexp::Terminal(Terminal {
+        type_,
+        contents,
+        literal: None,
+        trivia: vec![],
+        span: Span::new(offset, offset + contents.len()),
     })
 }
 
-pub fn t<T: Debug>(type_: T, contents: &str, offset: usize) -> Sexp<T> {
+/// A terminal carrying a decoded literal value alongside its raw source text
+pub fn lit<T: Debug>(type_: T, contents: &str, literal: Literal, offset: usize) -> Sexp<'_, T> {
     Sexp::Terminal(Terminal {
         type_,
         contents,
-        offset,
+        literal: Some(literal),
+        trivia: vec![],
+        span: Span::new(offset, offset + contents.len()),
     })
 }
@@ -1,70 +1,82 @@
-extern crate regex;
-
 mod compiler;
 mod vm;
-use compiler::file;
+use compiler::file::{self, File};
 use vm::chunk::Chunk;
-use vm::value::Value;
 use vm::VM;
 
-fn example_bytecode() -> Vec<u8> {
-    let mut chunk = Chunk::new();
-
-    // Source file path
-    chunk
-        .literals
-        .push(Value::String("test/file.emx".to_owned()));
-    chunk.literals.push(Value::Integer(120));
-    chunk.literals.push(Value::Integer(130));
-
-    // Bytecode
-    // [instruction, ...args, offset]
-    chunk.bytecode.push(vm::Op::LoadLiteral as u8);
-    chunk.bytecode.extend((1 as u32).to_be_bytes());
-    chunk.bytecode.extend((0 as u32).to_be_bytes());
-
-    chunk.bytecode.push(vm::Op::LoadLiteral as u8);
-    chunk.bytecode.extend((2 as u32).to_be_bytes());
-    chunk.bytecode.extend((0 as u32).to_be_bytes());
-
-    chunk.bytecode.push(vm::Op::Add as u8);
-    chunk.bytecode.extend((0 as u32).to_be_bytes());
-
-    chunk.bytecode.push(vm::Op::Return as u8);
-    chunk.bytecode.extend((1 as u32).to_be_bytes());
-
-    let code = chunk.to_bytecode();
-    code
-}
-
-fn interprete_example_bytecode() {
-    let bytes = example_bytecode();
+fn interprete(file: &file::RealFile, chunk: Chunk) {
+    let bytes = chunk.to_bytecode();
     let chunk = Chunk::from_bytecode(bytes);
-    let mut vm = VM::new();
-
-    println!("Disassembling bytecode...\n");
-    println!("{}", vm.disassemble(&chunk, "main"));
+    if let Err(error) = VM::new().interprete(chunk) {
+        let location = file.location(error.offset);
+        eprintln!("{}:{}: {}", file.path(), location, error);
+        std::process::exit(1);
+    }
+}
 
-    println!("Interpreting bytecode...\n");
-    let result = vm.interprete(chunk);
-    if let Err(error) = result {
-        eprintln!("{:?}", error);
+fn report_errors(file: &file::RealFile, errors: Vec<compiler::Error>) -> ! {
+    for error in errors {
+        let location = file.location(error.offset());
+        eprintln!("{}:{}: {}", file.path(), location, error);
     }
+    std::process::exit(1);
 }
 
 fn main() {
     let args = Args::new(std::env::args());
     let file = file::RealFile::new(args.file_path.clone());
-    compiler::compile(&file);
+
+    if args.dump_tokens {
+        match compiler::dump_tokens(&file, args.comments) {
+            Ok(tokens) => println!("{}", tokens),
+            Err(errors) => report_errors(&file, errors),
+        }
+        return;
+    }
+
+    match compiler::compile(&file, args.comments) {
+        Ok(chunk) if args.dump_bytecode => println!("{}", VM::new().disassemble(&chunk, "main")),
+        Ok(chunk) => interprete(&file, chunk),
+        Err(errors) => report_errors(&file, errors),
+    }
 }
 
 struct Args {
     file_path: String,
+    // Print the file's tokens, including trivia, instead of running it
+    dump_tokens: bool,
+    // Print the file's disassembled bytecode instead of running it
+    dump_bytecode: bool,
+    // Whether `#` starts a comment. Turning comments off with `--no-comments` makes them
+    // unexpected input, though a leading `#!` line is still skipped.
+    comments: bool,
 }
 
 impl Args {
-    pub fn new(mut args: std::env::Args) -> Self {
-        let file_path = args.nth(1).expect("path to file must be provided");
-        Args { file_path }
+    pub fn new(args: std::env::Args) -> Self {
+        let mut dump_tokens = false;
+        let mut dump_bytecode = false;
+        let mut comments = true;
+        let mut file_path = None;
+        // Options come before the file. Anything after it belongs to the script, e.g. when it's
+        // run directly through a `#!/usr/bin/env emerald` line.
+        for arg in args.skip(1) {
+            match arg.as_str() {
+                "--tokens" => dump_tokens = true,
+                "--bytecode" => dump_bytecode = true,
+                "--no-comments" => comments = false,
+                _ => {
+                    file_path = Some(arg);
+                    break;
+                }
+            }
+        }
+
+        Args {
+            file_path: file_path.expect("path to file must be provided"),
+            dump_tokens,
+            dump_bytecode,
+            comments,
+        }
     }
 }
@@ -15,6 +15,37 @@ pub enum Op {
     Return = 0,
     LoadLiteral,
     Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Pop,
+    ToString,
+    Concat,
+    Modulo,
+    Equal,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    GetLocal,
+    SetLocal,
+    GetGlobal,
+    SetGlobal,
+    Jump,
+    JumpIfFalse,
+    Call,
+    NoMatchingGuard,
+    MakeArray,
+    MakeHashmap,
+    GetConstant,
+    DefineType,
+    Construct,
+    GetField,
+    PushHandler,
+    PopHandler,
+    JumpIfNotInstance,
+    Swap,
+    Raise,
 }
 
 pub const MAX_INSTRUCTION_ARGS_COUNT: usize = 3;
@@ -27,92 +58,929 @@ impl TryFrom<u8> for Op {
             0x0 => Ok(Op::Return),
             0x1 => Ok(Op::LoadLiteral),
             0x2 => Ok(Op::Add),
+            0x3 => Ok(Op::Subtract),
+            0x4 => Ok(Op::Multiply),
+            0x5 => Ok(Op::Divide),
+            0x6 => Ok(Op::Pop),
+            0x7 => Ok(Op::ToString),
+            0x8 => Ok(Op::Concat),
+            0x9 => Ok(Op::Modulo),
+            0xA => Ok(Op::Equal),
+            0xB => Ok(Op::Less),
+            0xC => Ok(Op::LessEqual),
+            0xD => Ok(Op::Greater),
+            0xE => Ok(Op::GreaterEqual),
+            0xF => Ok(Op::GetLocal),
+            0x10 => Ok(Op::SetLocal),
+            0x11 => Ok(Op::GetGlobal),
+            0x12 => Ok(Op::SetGlobal),
+            0x13 => Ok(Op::Jump),
+            0x14 => Ok(Op::JumpIfFalse),
+            0x15 => Ok(Op::Call),
+            0x16 => Ok(Op::NoMatchingGuard),
+            0x17 => Ok(Op::MakeArray),
+            0x18 => Ok(Op::MakeHashmap),
+            0x19 => Ok(Op::GetConstant),
+            0x1A => Ok(Op::DefineType),
+            0x1B => Ok(Op::Construct),
+            0x1C => Ok(Op::GetField),
+            0x1D => Ok(Op::PushHandler),
+            0x1E => Ok(Op::PopHandler),
+            0x1F => Ok(Op::JumpIfNotInstance),
+            0x20 => Ok(Op::Swap),
+            0x21 => Ok(Op::Raise),
             _ => Err(format!("Invalid instruction: {}", byte)),
         }
     }
 }
 
-#[derive(Debug)]
-pub enum Error {
+// Named after the error types of the Ruby implementation
+#[allow(clippy::enum_variant_names)]
+#[derive(Clone, Debug)]
+pub enum ErrorKind {
     RuntimeError(String),
+    NameError(String),
+    ArgumentError(String),
+    TypeError(String),
+    NoMatchingGuardError(String),
+    // An instance of `Error` or one of its subtypes passed to `raise`
+    Raised(Object),
+}
+
+impl ErrorKind {
+    // The name of the error's type, which can be rescued like any other error type, and its
+    // message
+    fn parts(&self) -> (&str, String) {
+        match self {
+            ErrorKind::RuntimeError(message) => ("RuntimeError", message.clone()),
+            ErrorKind::NameError(message) => ("NameError", message.clone()),
+            ErrorKind::ArgumentError(message) => ("ArgumentError", message.clone()),
+            ErrorKind::TypeError(message) => ("TypeError", message.clone()),
+            ErrorKind::NoMatchingGuardError(message) => ("NoMatchingGuardError", message.clone()),
+            ErrorKind::Raised(object) => {
+                let message = object
+                    .fields
+                    .iter()
+                    .find(|(field, _)| field == "message")
+                    .map_or_else(String::new, |(_, message)| message.to_string());
+                (&object.type_.name, message)
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (name, message) = self.parts();
+        write!(f, "{}: {}", name, message)
+    }
+}
+
+/// An error which nothing rescued, along with the source offset of the instruction it was raised
+/// by. As with compile errors, the message leaves the location out.
+#[derive(Clone, Debug)]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub offset: usize,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.kind)
+    }
+}
+
+// The state of a function call: where to continue once it returns, and its parameters and
+// local variables
+struct Frame {
+    return_address: usize,
+    locals: Vec<Value>,
+}
+
+// A `try` block being run: where its `rescue` clauses start, and the call depth and stack size to
+// unwind to when an error is raised inside it
+struct Handler {
+    address: usize,
+    frames: usize,
+    stack: usize,
 }
 
 #[derive(Default)]
 pub struct VM {
     stack: Vec<Value>,
     // heap: Vec<Value>,
+    frames: Vec<Frame>,
+    handlers: Vec<Handler>,
+    globals: std::collections::HashMap<String, Value>,
+    // Types (and later modules), which live in their own namespace as in the Ruby implementation
+    constants: std::collections::HashMap<String, Value>,
     chunk: Chunk,
     ip: usize,
+    // The source offset of the instruction being run
+    offset: usize,
 }
 
 impl VM {
     pub fn new() -> VM {
-        VM::default()
+        let mut vm = VM::default();
+        let builtins = [
+            "String", "Array", "Hashmap", "Integer", "Float", "Symbol", "Boolean", "Nil",
+            "Function",
+        ];
+        for name in builtins {
+            vm.constants
+                .insert(name.to_owned(), Value::Type(Type::builtin(name)));
+        }
+        let error = Type {
+            name: "Error".to_owned(),
+            supertype: None,
+            fields: vec!["message".to_owned()],
+            constructable: true,
+        };
+        // The errors raised by the VM itself
+        let errors = [
+            "RuntimeError",
+            "NameError",
+            "ArgumentError",
+            "TypeError",
+            "NoMatchingGuardError",
+        ];
+        for name in errors {
+            let type_ = Type {
+                name: name.to_owned(),
+                supertype: Some(Box::new(error.clone())),
+                ..error.clone()
+            };
+            vm.constants.insert(name.to_owned(), Value::Type(type_));
+        }
+        vm.constants.insert("Error".to_owned(), Value::Type(error));
+        vm
     }
 
-    pub fn interprete(&mut self, chunk: Chunk) -> Result<(), Error> {
+    pub fn interprete(&mut self, chunk: Chunk) -> Result<Option<Value>, Error> {
         self.chunk = chunk;
         self.ip = 0;
         self.run()
     }
 
-    fn run(&mut self) -> Result<(), Error> {
+    // Runs until the program ends, resuming at the innermost `rescue` clauses whenever an error is
+    // raised inside a `try` block
+    fn run(&mut self) -> Result<Option<Value>, Error> {
+        loop {
+            match self.execute() {
+                Err(kind) => {
+                    let offset = self.offset;
+                    self.unwind(kind).map_err(|kind| Error { kind, offset })?
+                }
+                Ok(value) => return Ok(value),
+            }
+        }
+    }
+
+    fn execute(&mut self) -> Result<Option<Value>, ErrorKind> {
         loop {
             let byte = self.read_byte();
-            let op: Op = byte.try_into().map_err(|s| Error::RuntimeError(s))?;
+            let op: Op = byte.try_into().map_err(ErrorKind::RuntimeError)?;
 
-            // Debug stack
-            println!("{:?}", self.stack);
             match op {
-                Op::Return => return Ok(()),
+                Op::Return => {
+                    self.read_offset()?;
+                    match self.frames.pop() {
+                        Some(frame) => self.ip = frame.return_address,
+                        None => return Ok(self.stack.pop()),
+                    }
+                }
                 Op::LoadLiteral => {
-                    let index = self.read_u32().ok_or(Error::RuntimeError(
+                    let index = self.read_u32().ok_or(ErrorKind::RuntimeError(
                         "could not read literal index".to_string(),
                     ))?;
-                    let _line = self.read_u32();
+                    self.read_offset()?;
                     let literal = self.chunk.literals[index as usize].clone();
                     self.stack.push(literal);
                 }
-                Op::Add => {
-                    let a = self.stack.pop();
-                    if let Some(Value::Integer(a)) = a {
-                        let b = self.stack.pop();
-                        if let Some(Value::Integer(b)) = b {
-                            self.stack.push(Value::Integer(a + b));
-                        } else {
-                            return Err(Error::RuntimeError(format!(
-                                "invalid type {:?} for add",
-                                b
-                            )));
+                Op::Add | Op::Subtract | Op::Multiply | Op::Divide | Op::Modulo => {
+                    self.read_offset()?;
+                    let right = self.pop()?;
+                    let left = self.pop()?;
+                    self.stack.push(arithmetic(op, left, right)?);
+                }
+                Op::Pop => {
+                    self.read_offset()?;
+                    self.pop()?;
+                }
+                Op::Equal => {
+                    self.read_offset()?;
+                    let right = self.pop()?;
+                    let left = self.pop()?;
+                    self.stack.push(Value::Boolean(left == right));
+                }
+                Op::Less | Op::LessEqual | Op::Greater | Op::GreaterEqual => {
+                    self.read_offset()?;
+                    let right = self.pop()?;
+                    let left = self.pop()?;
+                    self.stack.push(comparison(op, left, right)?);
+                }
+                Op::GetLocal => {
+                    let slot = self.read_arg()? as usize;
+                    self.read_offset()?;
+                    let value = self.current_frame()?.locals.get(slot).cloned();
+                    self.stack.push(value.unwrap_or(Value::Nil));
+                }
+                Op::SetLocal => {
+                    let slot = self.read_arg()? as usize;
+                    self.read_offset()?;
+                    let value = self.peek()?.clone();
+                    let locals = &mut self.current_frame()?.locals;
+                    if slot >= locals.len() {
+                        locals.resize(slot + 1, Value::Nil);
+                    }
+                    locals[slot] = value;
+                }
+                Op::GetGlobal => {
+                    let name = self.read_name()?;
+                    self.read_offset()?;
+                    let value = self.globals.get(&name).cloned().ok_or_else(|| {
+                        ErrorKind::NameError(format!("No identifier with name {} found", name))
+                    })?;
+                    self.stack.push(value);
+                }
+                Op::SetGlobal => {
+                    let name = self.read_name()?;
+                    self.read_offset()?;
+                    let value = self.peek()?.clone();
+                    self.globals.insert(name, value);
+                }
+                Op::Jump => {
+                    let address = self.read_arg()?;
+                    self.read_offset()?;
+                    self.ip = address as usize;
+                }
+                Op::JumpIfFalse => {
+                    let address = self.read_arg()?;
+                    self.read_offset()?;
+                    if !self.pop()?.is_truthy() {
+                        self.ip = address as usize;
+                    }
+                }
+                Op::Call => {
+                    let count = self.read_arg()? as usize;
+                    self.read_offset()?;
+                    self.call(count)?;
+                }
+                Op::NoMatchingGuard => {
+                    self.read_offset()?;
+                    return Err(ErrorKind::NoMatchingGuardError(
+                        "No guard matched".to_string(),
+                    ));
+                }
+                Op::MakeArray => {
+                    let count = self.read_arg()? as usize;
+                    self.read_offset()?;
+                    let elements = self.pop_many(count)?;
+                    self.stack.push(Value::Array(elements));
+                }
+                Op::MakeHashmap => {
+                    let count = self.read_arg()? as usize;
+                    self.read_offset()?;
+                    let elements = self.pop_many(count)?;
+                    self.stack.push(Value::hashmap(elements));
+                }
+                Op::GetConstant => {
+                    let name = self.read_name()?;
+                    self.read_offset()?;
+                    let value = self.constants.get(&name).cloned().ok_or_else(|| {
+                        ErrorKind::NameError(format!("No constant with name {} found", name))
+                    })?;
+                    self.stack.push(value);
+                }
+                Op::DefineType => {
+                    let name = self.read_name()?;
+                    self.read_offset()?;
+                    let fields = self.pop()?;
+                    let supertype = self.pop()?;
+                    let type_ = self.define_type(name, supertype, fields)?;
+                    self.stack.push(type_);
+                }
+                Op::Construct => {
+                    let count = self.read_arg()? as usize;
+                    self.read_offset()?;
+                    let args = self.pop_many(count)?;
+                    let object = construct(self.pop()?, args)?;
+                    self.stack.push(object);
+                }
+                Op::GetField => {
+                    self.read_offset()?;
+                    let key = self.pop()?;
+                    let value = self.pop()?;
+                    self.stack.push(get_field(value, key)?);
+                }
+                Op::PushHandler => {
+                    let address = self.read_arg()? as usize;
+                    self.read_offset()?;
+                    self.handlers.push(Handler {
+                        address,
+                        frames: self.frames.len(),
+                        stack: self.stack.len(),
+                    });
+                }
+                Op::PopHandler => {
+                    self.read_offset()?;
+                    self.handlers.pop();
+                }
+                Op::JumpIfNotInstance => {
+                    let address = self.read_arg()?;
+                    self.read_offset()?;
+                    let type_ = self.pop()?;
+                    if !is_instance(self.peek()?, &type_) {
+                        self.ip = address as usize;
+                    }
+                }
+                Op::Swap => {
+                    self.read_offset()?;
+                    let length = self.stack.len();
+                    if length < 2 {
+                        return Err(ErrorKind::RuntimeError("stack underflow".to_string()));
+                    }
+                    self.stack.swap(length - 1, length - 2);
+                }
+                Op::Raise => {
+                    self.read_offset()?;
+                    return Err(self.raise()?);
+                }
+                Op::ToString => {
+                    self.read_offset()?;
+                    let value = self.pop()?;
+                    self.stack.push(Value::String(value.to_string()));
+                }
+                Op::Concat => {
+                    let count = self.read_u32().ok_or(ErrorKind::RuntimeError(
+                        "could not read string count".to_string(),
+                    ))?;
+                    self.read_offset()?;
+                    let start = self
+                        .stack
+                        .len()
+                        .checked_sub(count as usize)
+                        .ok_or_else(|| ErrorKind::RuntimeError("stack underflow".to_string()))?;
+                    let mut result = String::new();
+                    for value in self.stack.drain(start..) {
+                        match value {
+                            Value::String(string) => result.push_str(&string),
+                            value => {
+                                return Err(ErrorKind::RuntimeError(format!(
+                                    "invalid type {:?} for {:?}",
+                                    value, op
+                                )))
+                            }
                         }
-                    } else {
-                        return Err(Error::RuntimeError(format!("invalid type {:?} for add", a)));
                     }
+                    self.stack.push(Value::String(result));
                 }
             };
         }
     }
 
+    // Calls the value below the top `count` values on the stack with them as arguments. As in
+    // the Ruby implementation, calling anything other than a function without arguments simply
+    // gives back the value itself.
+    fn call(&mut self, count: usize) -> Result<(), ErrorKind> {
+        let start = self
+            .stack
+            .len()
+            .checked_sub(count + 1)
+            .ok_or_else(|| ErrorKind::RuntimeError("stack underflow".to_string()))?;
+        // Bare identifiers are all called, so this is checked before anything is moved
+        if count == 0 && !matches!(self.stack[start], Value::Function(_)) {
+            return Ok(());
+        }
+        let mut values = self.stack.split_off(start);
+        let args = values.split_off(1);
+        let callee = values.pop().unwrap();
+
+        match callee {
+            Value::Function(function) if function.arity as usize == count => {
+                self.frames.push(Frame {
+                    return_address: self.ip,
+                    locals: args,
+                });
+                self.ip = function.address as usize;
+                Ok(())
+            }
+            Value::Function(function) => Err(ErrorKind::ArgumentError(format!(
+                "Invalid number of arguments for <fn: {} ({})>, expected {}, got {}",
+                function.name, function.arity, function.arity, count
+            ))),
+            value => Err(ErrorKind::RuntimeError(format!(
+                "{:?} is not a function",
+                value
+            ))),
+        }
+    }
+
+    // Gives the error to raise for the value on top of the stack, which has to be an instance of
+    // `Error`
+    fn raise(&mut self) -> Result<ErrorKind, ErrorKind> {
+        let value = self.pop()?;
+        match value {
+            Value::Object(object) if is_instance(&value, &self.constants["Error"]) => {
+                Ok(ErrorKind::Raised(object))
+            }
+            value => Err(ErrorKind::TypeError(format!(
+                "Expected type Error, got {}",
+                value.inspect()
+            ))),
+        }
+    }
+
+    // Resumes at the innermost `try` block's rescue clauses with the error on the stack, or gives
+    // the error back when there's nothing to rescue it
+    fn unwind(&mut self, error: ErrorKind) -> Result<(), ErrorKind> {
+        let handler = self.handlers.pop().ok_or_else(|| error.clone())?;
+        self.frames.truncate(handler.frames);
+        self.stack.truncate(handler.stack);
+        let value = match error {
+            ErrorKind::Raised(object) => Value::Object(object),
+            error => {
+                let (name, message) = error.parts();
+                let Value::Type(type_) = self.constants[name].clone() else {
+                    unreachable!("{} is a builtin type", name)
+                };
+                Value::Object(Object {
+                    type_,
+                    fields: vec![("message".to_owned(), Value::String(message))],
+                })
+            }
+        };
+        self.stack.push(value);
+        self.ip = handler.address;
+        Ok(())
+    }
+
+    // Creates a type named `name` with the given fields, after any it inherits from `supertype`
+    fn define_type(
+        &mut self,
+        name: String,
+        supertype: Value,
+        fields: Value,
+    ) -> Result<Value, ErrorKind> {
+        if self.constants.contains_key(&name) {
+            return Err(ErrorKind::NameError(format!(
+                "type {} is already defined",
+                name
+            )));
+        }
+
+        let supertype = match supertype {
+            Value::Nil => None,
+            Value::Type(supertype) => Some(Box::new(supertype)),
+            value => {
+                return Err(ErrorKind::TypeError(format!(
+                    "Expected a type to inherit from, got {}",
+                    value
+                )))
+            }
+        };
+        let mut all_fields = supertype
+            .as_ref()
+            .map_or_else(Vec::new, |supertype| supertype.fields.clone());
+        let Value::Array(fields) = fields else {
+            return Err(ErrorKind::TypeError(
+                "Expected an array of fields".to_string(),
+            ));
+        };
+        for field in fields {
+            match field {
+                Value::Symbol(field) => all_fields.push(field),
+                value => {
+                    return Err(ErrorKind::TypeError(format!(
+                        "Expected type Symbol for field, got {}",
+                        value.inspect()
+                    )))
+                }
+            }
+        }
+
+        let type_ = Value::Type(Type {
+            name: name.clone(),
+            supertype,
+            fields: all_fields,
+            constructable: true,
+        });
+        self.constants.insert(name, type_.clone());
+        Ok(type_)
+    }
+
+    fn current_frame(&mut self) -> Result<&mut Frame, ErrorKind> {
+        self.frames.last_mut().ok_or_else(|| {
+            ErrorKind::RuntimeError("no local variables outside a function".to_string())
+        })
+    }
+
+    fn peek(&self) -> Result<&Value, ErrorKind> {
+        self.stack
+            .last()
+            .ok_or_else(|| ErrorKind::RuntimeError("stack underflow".to_string()))
+    }
+
+    fn pop(&mut self) -> Result<Value, ErrorKind> {
+        self.stack
+            .pop()
+            .ok_or_else(|| ErrorKind::RuntimeError("stack underflow".to_string()))
+    }
+
+    // Pops the top `count` values, in the order they were pushed
+    fn pop_many(&mut self, count: usize) -> Result<Vec<Value>, ErrorKind> {
+        let start = self
+            .stack
+            .len()
+            .checked_sub(count)
+            .ok_or_else(|| ErrorKind::RuntimeError("stack underflow".to_string()))?;
+        Ok(self.stack.split_off(start))
+    }
+
     fn read_byte(&mut self) -> u8 {
         let byte = self.chunk.bytecode[self.ip];
         self.ip += 1;
         byte
     }
 
-    fn read_u32(&mut self) -> Option<u32> {
-        if let Some(num) = byte_reader::read_u32(&self.chunk.bytecode[self.ip..]) {
-            self.ip += 4;
-            return Some(num);
-        } else {
-            return None;
+    fn read_arg(&mut self) -> Result<u32, ErrorKind> {
+        self.read_u32().ok_or_else(|| {
+            ErrorKind::RuntimeError("could not read instruction argument".to_string())
+        })
+    }
+
+    // Reads the source offset each instruction ends with
+    fn read_offset(&mut self) -> Result<(), ErrorKind> {
+        let offset = self.read_u32().ok_or_else(|| {
+            ErrorKind::RuntimeError("could not read instruction offset".to_string())
+        })?;
+        self.offset = offset as usize;
+        Ok(())
+    }
+
+    // Reads a literal index argument referring to the name of a global
+    fn read_name(&mut self) -> Result<String, ErrorKind> {
+        let index = self.read_arg()?;
+        match &self.chunk.literals[index as usize] {
+            Value::String(name) => Ok(name.clone()),
+            literal => Err(ErrorKind::RuntimeError(format!(
+                "invalid global name {:?}",
+                literal
+            ))),
         }
     }
 
+    fn read_u32(&mut self) -> Option<u32> {
+        let num = byte_reader::read_u32(&self.chunk.bytecode[self.ip..])?;
+        self.ip += 4;
+        Some(num)
+    }
+
     // fn peek_byte(&mut self) -> u8 {
     //     self.chunk.bytecode[self.ip]
     // }
 
     pub fn disassemble(&self, chunk: &Chunk, name: &'static str) -> String {
-        disassembler::disassemble_chunk(&chunk, name)
+        disassembler::disassemble_chunk(chunk, name)
+    }
+}
+
+// Builds an instance of `type_`, from either a single hashmap of field values, or from the field
+// values in order. Fields without a value are nil, and any extra arguments are ignored.
+fn construct(type_: Value, args: Vec<Value>) -> Result<Value, ErrorKind> {
+    let type_ = match type_ {
+        Value::Type(type_) if type_.constructable => type_,
+        Value::Type(type_) => {
+            return Err(ErrorKind::TypeError(format!(
+                "Type `{}` is not constructable",
+                type_.name
+            )))
+        }
+        value => return Err(ErrorKind::TypeError(format!("{} is not a type", value))),
+    };
+
+    let mut fields: Vec<_> = type_
+        .fields
+        .iter()
+        .map(|field| (field.clone(), Value::Nil))
+        .collect();
+    match &args[..] {
+        [Value::Hashmap(pairs)] => {
+            for (key, value) in pairs {
+                let field = fields.iter_mut().find(|(field, _)| match key {
+                    Value::Symbol(key) => key == field,
+                    _ => false,
+                });
+                match field {
+                    Some(field) => field.1 = value.clone(),
+                    None => {
+                        return Err(ErrorKind::ArgumentError(format!(
+                            "{} has no field {}",
+                            type_.name,
+                            key.inspect()
+                        )))
+                    }
+                }
+            }
+        }
+        args => {
+            for (field, value) in fields.iter_mut().zip(args) {
+                field.1 = value.clone();
+            }
+        }
+    }
+
+    Ok(Value::Object(Object { type_, fields }))
+}
+
+fn is_instance(value: &Value, type_: &Value) -> bool {
+    match (value, type_) {
+        (Value::Object(object), Value::Type(type_)) => object.type_.is_subtype_of(type_),
+        _ => false,
+    }
+}
+
+// Looks up a key of a hashmap, which is nil when missing, or a field of an instance
+fn get_field(value: Value, key: Value) -> Result<Value, ErrorKind> {
+    match (value, key) {
+        (Value::Hashmap(pairs), key) => Ok(pairs
+            .into_iter()
+            .find(|(existing, _)| *existing == key)
+            .map_or(Value::Nil, |(_, value)| value)),
+        (Value::Object(object), Value::Symbol(name)) => object
+            .fields
+            .iter()
+            .find(|(field, _)| *field == name)
+            .map(|(_, value)| value.clone())
+            .ok_or_else(|| {
+                ErrorKind::NameError(format!("{} has no field :{}", object.type_.name, name))
+            }),
+        (value, key) => Err(ErrorKind::TypeError(format!(
+            "Cannot look up {} in {}",
+            key.inspect(),
+            value.inspect()
+        ))),
+    }
+}
+
+// Integer arithmetic stays integral (dividing rounds towards negative infinity, as in the Ruby
+// implementation), while mixing an integer with a float promotes the integer to a float.
+fn arithmetic(op: Op, left: Value, right: Value) -> Result<Value, ErrorKind> {
+    match (left, right) {
+        (Value::Integer(a), Value::Integer(b)) => {
+            let result = match op {
+                Op::Add => a.checked_add(b),
+                Op::Subtract => a.checked_sub(b),
+                Op::Multiply => a.checked_mul(b),
+                Op::Divide if b == 0 => {
+                    return Err(ErrorKind::RuntimeError("divided by 0".to_string()))
+                }
+                Op::Divide => floor_div(a, b),
+                Op::Modulo if b == 0 => {
+                    return Err(ErrorKind::RuntimeError("divided by 0".to_string()))
+                }
+                Op::Modulo => floor_mod(a, b),
+                _ => unreachable!("{:?} is not an arithmetic op", op),
+            };
+            result.map(Value::Integer).ok_or_else(|| {
+                ErrorKind::RuntimeError(format!("integer overflow in {:?} of {} and {}", op, a, b))
+            })
+        }
+        (Value::Integer(a), Value::Float(b)) => float_arithmetic(op, a as f64, b),
+        (Value::Float(a), Value::Integer(b)) => float_arithmetic(op, a, b as f64),
+        (Value::Float(a), Value::Float(b)) => float_arithmetic(op, a, b),
+        (Value::Integer(_), b) | (Value::Float(_), b) => Err(ErrorKind::RuntimeError(format!(
+            "invalid type {:?} for {:?}",
+            b, op
+        ))),
+        (a, _) => Err(ErrorKind::RuntimeError(format!(
+            "invalid type {:?} for {:?}",
+            a, op
+        ))),
+    }
+}
+
+// Dividing by zero is an error, as it is for integers, rather than giving an infinity or NaN
+fn float_arithmetic(op: Op, a: f64, b: f64) -> Result<Value, ErrorKind> {
+    let result = match op {
+        Op::Add => a + b,
+        Op::Subtract => a - b,
+        Op::Multiply => a * b,
+        Op::Divide | Op::Modulo if b == 0.0 => {
+            return Err(ErrorKind::RuntimeError("divided by 0".to_string()))
+        }
+        Op::Divide => a / b,
+        Op::Modulo => a - b * (a / b).floor(),
+        _ => unreachable!("{:?} is not an arithmetic op", op),
+    };
+    Ok(Value::Float(result))
+}
+
+// Numbers of either type can be compared with each other. Integers are compared as they are,
+// since converting them to floats loses precision past 2^53.
+fn comparison(op: Op, left: Value, right: Value) -> Result<Value, ErrorKind> {
+    let ordering = match (left, right) {
+        (Value::Integer(a), Value::Integer(b)) => a.partial_cmp(&b),
+        (Value::Integer(a), Value::Float(b)) => (a as f64).partial_cmp(&b),
+        (Value::Float(a), Value::Integer(b)) => a.partial_cmp(&(b as f64)),
+        (Value::Float(a), Value::Float(b)) => a.partial_cmp(&b),
+        (Value::Integer(_), b) | (Value::Float(_), b) => {
+            return Err(ErrorKind::RuntimeError(format!(
+                "invalid type {:?} for {:?}",
+                b, op
+            )))
+        }
+        (a, _) => {
+            return Err(ErrorKind::RuntimeError(format!(
+                "invalid type {:?} for {:?}",
+                a, op
+            )))
+        }
+    };
+
+    // NaN is neither less than, equal to, nor greater than anything
+    let result = ordering.is_some_and(|ordering| match op {
+        Op::Less => ordering.is_lt(),
+        Op::LessEqual => ordering.is_le(),
+        Op::Greater => ordering.is_gt(),
+        Op::GreaterEqual => ordering.is_ge(),
+        _ => unreachable!("{:?} is not a comparison op", op),
+    });
+    Ok(Value::Boolean(result))
+}
+
+// The remainder takes the sign of the divisor, so that it's consistent with `floor_div`
+fn floor_mod(a: i64, b: i64) -> Option<i64> {
+    let remainder = a.checked_rem(b)?;
+    if remainder != 0 && (remainder < 0) != (b < 0) {
+        Some(remainder + b)
+    } else {
+        Some(remainder)
+    }
+}
+
+fn floor_div(a: i64, b: i64) -> Option<i64> {
+    let quotient = a.checked_div(b)?;
+    if a % b != 0 && (a < 0) != (b < 0) {
+        Some(quotient - 1)
+    } else {
+        Some(quotient)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(op: Op, left: Value, right: Value) -> Result<Value, ErrorKind> {
+        let mut chunk = Chunk::new();
+        chunk.literals.push(left);
+        chunk.literals.push(right);
+        for index in 0..2u32 {
+            chunk.bytecode.push(Op::LoadLiteral as u8);
+            chunk.bytecode.extend(index.to_be_bytes());
+            chunk.bytecode.extend(0u32.to_be_bytes());
+        }
+        for op in [op, Op::Return] {
+            chunk.bytecode.push(op as u8);
+            chunk.bytecode.extend(0u32.to_be_bytes());
+        }
+
+        let chunk = Chunk::from_bytecode(chunk.to_bytecode());
+        VM::new()
+            .interprete(chunk)
+            .map(Option::unwrap)
+            .map_err(|error| error.kind)
+    }
+
+    #[test]
+    fn integer_arithmetic() {
+        let result = |op, a, b| run(op, Value::Integer(a), Value::Integer(b)).unwrap();
+        assert_eq!(result(Op::Add, 1, 2), Value::Integer(3));
+        assert_eq!(result(Op::Subtract, 1, 2), Value::Integer(-1));
+        assert_eq!(result(Op::Multiply, 3, -4), Value::Integer(-12));
+        assert_eq!(result(Op::Divide, 7, 2), Value::Integer(3));
+        assert_eq!(result(Op::Divide, -7, 2), Value::Integer(-4));
+        assert_eq!(result(Op::Divide, 7, -2), Value::Integer(-4));
+    }
+
+    #[test]
+    fn mixed_integer_and_float_arithmetic() {
+        assert_eq!(
+            run(Op::Add, Value::Integer(1), Value::Float(2.5)).unwrap(),
+            Value::Float(3.5)
+        );
+        assert_eq!(
+            run(Op::Subtract, Value::Float(2.5), Value::Integer(1)).unwrap(),
+            Value::Float(1.5)
+        );
+        assert_eq!(
+            run(Op::Multiply, Value::Float(0.5), Value::Float(0.5)).unwrap(),
+            Value::Float(0.25)
+        );
+        assert_eq!(
+            run(Op::Divide, Value::Integer(7), Value::Float(2.0)).unwrap(),
+            Value::Float(3.5)
+        );
+    }
+
+    #[test]
+    fn modulo_takes_the_sign_of_the_divisor() {
+        let result = |a, b| run(Op::Modulo, Value::Integer(a), Value::Integer(b)).unwrap();
+        assert_eq!(result(7, 2), Value::Integer(1));
+        assert_eq!(result(-7, 2), Value::Integer(1));
+        assert_eq!(result(7, -2), Value::Integer(-1));
+        assert_eq!(
+            run(Op::Modulo, Value::Float(-7.5), Value::Integer(2)).unwrap(),
+            Value::Float(0.5)
+        );
+    }
+
+    #[test]
+    fn comparisons() {
+        let result = |op, a, b| run(op, a, b).unwrap();
+        assert_eq!(
+            result(Op::Less, Value::Integer(1), Value::Float(1.5)),
+            Value::Boolean(true)
+        );
+        assert_eq!(
+            result(Op::GreaterEqual, Value::Integer(1), Value::Integer(2)),
+            Value::Boolean(false)
+        );
+        assert_eq!(
+            result(
+                Op::Equal,
+                Value::String("a".to_owned()),
+                Value::String("a".to_owned())
+            ),
+            Value::Boolean(true)
+        );
+        assert_eq!(
+            result(Op::Equal, Value::Integer(1), Value::Float(1.0)),
+            Value::Boolean(true)
+        );
+        assert_eq!(
+            result(Op::Equal, Value::Integer(1), Value::Nil),
+            Value::Boolean(false)
+        );
+    }
+
+    #[test]
+    fn compares_large_integers_exactly() {
+        let (a, b) = (9007199254740993, 9007199254740992);
+        assert_eq!(
+            run(Op::Greater, Value::Integer(a), Value::Integer(b)).unwrap(),
+            Value::Boolean(true)
+        );
+        assert_eq!(
+            run(Op::LessEqual, Value::Integer(a), Value::Integer(b)).unwrap(),
+            Value::Boolean(false)
+        );
+        assert_eq!(
+            run(
+                Op::Less,
+                Value::Integer(i64::MAX - 1),
+                Value::Integer(i64::MAX)
+            )
+            .unwrap(),
+            Value::Boolean(true)
+        );
+    }
+
+    #[test]
+    fn arithmetic_errors() {
+        let error = |op, a, b| match run(op, a, b) {
+            Err(ErrorKind::RuntimeError(message)) => message,
+            result => panic!("expected a runtime error, got {:?}", result),
+        };
+        assert_eq!(
+            error(Op::Divide, Value::Integer(1), Value::Integer(0)),
+            "divided by 0"
+        );
+        assert_eq!(
+            error(Op::Divide, Value::Float(1.0), Value::Float(0.0)),
+            "divided by 0"
+        );
+        assert_eq!(
+            error(Op::Divide, Value::Integer(1), Value::Float(-0.0)),
+            "divided by 0"
+        );
+        assert_eq!(
+            error(Op::Modulo, Value::Integer(1), Value::Integer(0)),
+            "divided by 0"
+        );
+        assert_eq!(
+            error(Op::Modulo, Value::Float(1.0), Value::Float(0.0)),
+            "divided by 0"
+        );
+        assert_eq!(
+            error(Op::Add, Value::Integer(i64::MAX), Value::Integer(1)),
+            "integer overflow in Add of 9223372036854775807 and 1"
+        );
+        assert_eq!(
+            error(Op::Add, Value::Float(1.0), Value::String("a".to_owned())),
+            "invalid type String(\"a\") for Add"
+        );
     }
 }
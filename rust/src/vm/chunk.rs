@@ -78,7 +78,20 @@ mod chunk_parser {
         // Literal layout
         //
         // Integer: 1 byte tag, 8 bytes of data
+        // Float: 1 byte tag, 8 bytes of data
         // String: 1 byte tag, 8 bytes of size, variable number of bytes for data
+        // Boolean: 1 byte tag, 1 byte of data
+        // Nil: 1 byte tag
+        // Function: 1 byte tag, 4 bytes of arity, 4 bytes of address, 8 bytes of name size,
+        //   variable number of bytes for the name
+        // Symbol: 1 byte tag, 8 bytes of size, variable number of bytes for the name
+        // Array: 1 byte tag, 8 bytes of length, then each element as a literal
+        // Hashmap: 1 byte tag, 8 bytes of length, then each key followed by its value as literals
+        // Type: 1 byte tag, 1 byte constructable flag, 8 bytes of name size, variable number of
+        //   bytes for the name, 8 bytes of field count, then each field name as 8 bytes of size
+        //   and its bytes, then the supertype as a Type literal (or Nil if there isn't one)
+        // Object: 1 byte tag, its type as a Type literal, 8 bytes of field count, then each field
+        //   name as 8 bytes of size and its bytes, followed by the field's value as a literal
         //
         pub fn parse_chunk(mut self) -> Result<Chunk, String> {
             self.read_magic()?;
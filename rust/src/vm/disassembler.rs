@@ -25,7 +25,10 @@ impl<'a> Disassembler<'a> {
     fn disassemble_literals(&mut self) -> String {
         let mut result = format!("{}.data:\n", self.chunk_name);
         for (index, lit) in self.chunk.literals.iter().enumerate() {
-            result += &format!("  {:04} {}\n", index, lit);
+            result += &match lit {
+                Value::String(string) => format!("  {:04} {:?}\n", index, string),
+                lit => format!("  {:04} {}\n", index, lit),
+            };
         }
         result + "\n"
     }
@@ -39,6 +42,37 @@ impl<'a> Disassembler<'a> {
                 Op::Return => self.disassemble_instruction("Return", 0),
                 Op::LoadLiteral => self.disassemble_instruction("LoadLit", 1),
                 Op::Add => self.disassemble_instruction("Add", 0),
+                Op::Subtract => self.disassemble_instruction("Subtract", 0),
+                Op::Multiply => self.disassemble_instruction("Multiply", 0),
+                Op::Divide => self.disassemble_instruction("Divide", 0),
+                Op::Pop => self.disassemble_instruction("Pop", 0),
+                Op::ToString => self.disassemble_instruction("ToString", 0),
+                Op::Concat => self.disassemble_instruction("Concat", 1),
+                Op::Modulo => self.disassemble_instruction("Modulo", 0),
+                Op::Equal => self.disassemble_instruction("Equal", 0),
+                Op::Less => self.disassemble_instruction("Less", 0),
+                Op::LessEqual => self.disassemble_instruction("LessEq", 0),
+                Op::Greater => self.disassemble_instruction("Greater", 0),
+                Op::GreaterEqual => self.disassemble_instruction("GreatEq", 0),
+                Op::GetLocal => self.disassemble_instruction("GetLocal", 1),
+                Op::SetLocal => self.disassemble_instruction("SetLocal", 1),
+                Op::GetGlobal => self.disassemble_instruction("GetGlob", 1),
+                Op::SetGlobal => self.disassemble_instruction("SetGlob", 1),
+                Op::Jump => self.disassemble_instruction("Jump", 1),
+                Op::JumpIfFalse => self.disassemble_instruction("JumpIfF", 1),
+                Op::Call => self.disassemble_instruction("Call", 1),
+                Op::NoMatchingGuard => self.disassemble_instruction("NoGuard", 0),
+                Op::MakeArray => self.disassemble_instruction("MkArray", 1),
+                Op::MakeHashmap => self.disassemble_instruction("MkHash", 1),
+                Op::GetConstant => self.disassemble_instruction("GetConst", 1),
+                Op::DefineType => self.disassemble_instruction("DefType", 1),
+                Op::Construct => self.disassemble_instruction("New", 1),
+                Op::GetField => self.disassemble_instruction("GetField", 0),
+                Op::PushHandler => self.disassemble_instruction("PushHdlr", 1),
+                Op::PopHandler => self.disassemble_instruction("PopHdlr", 0),
+                Op::JumpIfNotInstance => self.disassemble_instruction("JumpIfNI", 1),
+                Op::Swap => self.disassemble_instruction("Swap", 0),
+                Op::Raise => self.disassemble_instruction("Raise", 0),
             };
         }
         result + "\n"
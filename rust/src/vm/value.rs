@@ -2,32 +2,238 @@ use crate::vm::byte_reader;
 
 #[derive(Clone, Debug)]
 pub enum Value {
-    Integer(u64),
+    Integer(i64),
+    Float(f64),
     String(String),
+    Boolean(bool),
+    Nil,
+    Function(Function),
+    Symbol(String),
+    Array(Vec<Value>),
+    // Key and value pairs in the order the keys were first inserted
+    Hashmap(Vec<(Value, Value)>),
+    Type(Type),
+    Object(Object),
 }
 
+/// A function compiled into the chunk's bytecode, starting at `address`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub arity: u32,
+    pub address: u32,
+}
+
+/// A builtin type, or one defined with `deftype`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Type {
+    pub name: String,
+    pub supertype: Option<Box<Type>>,
+    // Set by the constructor's arguments in order, including those inherited from the supertype
+    pub fields: Vec<String>,
+    pub constructable: bool,
+}
+
+impl Type {
+    pub fn builtin(name: &str) -> Type {
+        Type {
+            name: name.to_owned(),
+            supertype: None,
+            fields: vec![],
+            constructable: false,
+        }
+    }
+
+    // Whether this is `other`, or inherits from it
+    pub fn is_subtype_of(&self, other: &Type) -> bool {
+        self == other
+            || self
+                .supertype
+                .as_ref()
+                .is_some_and(|supertype| supertype.is_subtype_of(other))
+    }
+}
+
+/// An instance of a constructable type, holding a value for each of the type's fields
+#[derive(Clone, Debug, PartialEq)]
+pub struct Object {
+    pub type_: Type,
+    pub fields: Vec<(String, Value)>,
+}
+
+// Integers and floats are equal when they hold the same number, as in arithmetic, where the two
+// mix freely. Other values are only equal to values of the same kind.
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Integer(a), Value::Integer(b)) => a == b,
+            (Value::Float(a), Value::Float(b)) => a == b,
+            (Value::Integer(a), Value::Float(b)) | (Value::Float(b), Value::Integer(a)) => {
+                integer_equals_float(*a, *b)
+            }
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Boolean(a), Value::Boolean(b)) => a == b,
+            (Value::Nil, Value::Nil) => true,
+            (Value::Function(a), Value::Function(b)) => a == b,
+            (Value::Symbol(a), Value::Symbol(b)) => a == b,
+            (Value::Array(a), Value::Array(b)) => a == b,
+            (Value::Hashmap(a), Value::Hashmap(b)) => a == b,
+            (Value::Type(a), Value::Type(b)) => a == b,
+            (Value::Object(a), Value::Object(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+// Compares exactly, where converting the integer to a float could round it to a different number
+fn integer_equals_float(integer: i64, float: f64) -> bool {
+    // -2^63 converts exactly, while i64::MAX rounds up to 2^63, which is out of range
+    float.fract() == 0.0
+        && float >= i64::MIN as f64
+        && float < i64::MAX as f64
+        && float as i64 == integer
+}
+
+// The form values take when converted to strings, e.g. by string interpolation
 impl std::fmt::Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Value::Integer(x) => write!(f, "{}", x),
-            Value::String(x) => write!(f, "\"{}\"", x),
+            Value::Float(x) => write!(f, "{:?}", x),
+            Value::String(x) => write!(f, "{}", x),
+            Value::Boolean(x) => write!(f, "{}", x),
+            Value::Nil => write!(f, "nil"),
+            Value::Function(function) => {
+                write!(f, "<fn: {} ({})>", function.name, function.arity)
+            }
+            Value::Symbol(x) => write!(f, "{}", x),
+            Value::Array(elements) => {
+                let elements: Vec<_> = elements.iter().map(Value::inspect).collect();
+                write!(f, "[{}]", elements.join(" "))
+            }
+            Value::Hashmap(pairs) => {
+                let pairs: Vec<_> = pairs
+                    .iter()
+                    .map(|(key, value)| format!("{} {}", key.inspect(), value.inspect()))
+                    .collect();
+                write!(f, "{{{}}}", pairs.join(" "))
+            }
+            Value::Type(type_) => write!(f, "{}", type_.name),
+            Value::Object(object) if object.fields.is_empty() => {
+                write!(f, "{}", object.type_.name)
+            }
+            Value::Object(object) => {
+                let fields: Vec<_> = object
+                    .fields
+                    .iter()
+                    .map(|(name, value)| format!(":{} {}", name, value))
+                    .collect();
+                write!(f, "{} {{{}}}", object.type_.name, fields.join(", "))
+            }
         }
     }
 }
 
 impl Value {
+    /// Builds a hashmap from alternating keys and values, where a repeated key keeps its first
+    /// position but takes the last value given for it
+    pub fn hashmap(elements: Vec<Value>) -> Value {
+        let mut pairs: Vec<(Value, Value)> = vec![];
+        let mut elements = elements.into_iter();
+        while let (Some(key), Some(value)) = (elements.next(), elements.next()) {
+            match pairs.iter_mut().find(|(existing, _)| *existing == key) {
+                Some(pair) => pair.1 = value,
+                None => pairs.push((key, value)),
+            }
+        }
+        Value::Hashmap(pairs)
+    }
+
+    // The form values take inside arrays and hashmaps, where strings and symbols are shown as
+    // they would be written
+    pub fn inspect(&self) -> String {
+        match self {
+            Value::String(x) => format!("{:?}", x),
+            Value::Symbol(x) => format!(":{}", x),
+            value => value.to_string(),
+        }
+    }
+
+    // Only nil and false are falsy
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Boolean(false))
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = vec![];
 
         match self {
             Value::Integer(x) => {
-                bytes.push(Type::Integer as u8);
+                bytes.push(Tag::Integer as u8);
                 bytes.extend(x.to_be_bytes());
             }
+            Value::Float(x) => {
+                bytes.push(Tag::Float as u8);
+                bytes.extend(x.to_bits().to_be_bytes());
+            }
             Value::String(x) => {
-                bytes.push(Type::String as u8);
-                bytes.extend((x.bytes().len() as u64).to_be_bytes());
-                bytes.extend(x.bytes().collect::<Vec<u8>>());
+                bytes.push(Tag::String as u8);
+                bytes.extend((x.len() as u64).to_be_bytes());
+                bytes.extend(x.bytes());
+            }
+            Value::Boolean(x) => {
+                bytes.push(Tag::Boolean as u8);
+                bytes.push(*x as u8);
+            }
+            Value::Nil => bytes.push(Tag::Nil as u8),
+            Value::Function(function) => {
+                bytes.push(Tag::Function as u8);
+                bytes.extend(function.arity.to_be_bytes());
+                bytes.extend(function.address.to_be_bytes());
+                bytes.extend((function.name.len() as u64).to_be_bytes());
+                bytes.extend(function.name.bytes());
+            }
+            Value::Symbol(x) => {
+                bytes.push(Tag::Symbol as u8);
+                bytes.extend((x.len() as u64).to_be_bytes());
+                bytes.extend(x.bytes());
+            }
+            Value::Array(elements) => {
+                bytes.push(Tag::Array as u8);
+                bytes.extend((elements.len() as u64).to_be_bytes());
+                for element in elements {
+                    bytes.extend(element.to_bytes());
+                }
+            }
+            Value::Hashmap(pairs) => {
+                bytes.push(Tag::Hashmap as u8);
+                bytes.extend((pairs.len() as u64).to_be_bytes());
+                for (key, value) in pairs {
+                    bytes.extend(key.to_bytes());
+                    bytes.extend(value.to_bytes());
+                }
+            }
+            Value::Type(type_) => {
+                bytes.push(Tag::Type as u8);
+                bytes.push(type_.constructable as u8);
+                write_str(&mut bytes, &type_.name);
+                bytes.extend((type_.fields.len() as u64).to_be_bytes());
+                for field in &type_.fields {
+                    write_str(&mut bytes, field);
+                }
+                match &type_.supertype {
+                    Some(supertype) => bytes.extend(Value::Type(*supertype.clone()).to_bytes()),
+                    None => bytes.extend(Value::Nil.to_bytes()),
+                }
+            }
+            Value::Object(object) => {
+                bytes.push(Tag::Object as u8);
+                bytes.extend(Value::Type(object.type_.clone()).to_bytes());
+                bytes.extend((object.fields.len() as u64).to_be_bytes());
+                for (name, value) in &object.fields {
+                    write_str(&mut bytes, name);
+                    bytes.extend(value.to_bytes());
+                }
             }
         };
 
@@ -36,14 +242,21 @@ impl Value {
 
     pub fn from_bytes(bytes: &[u8]) -> Result<(usize, Value), String> {
         match bytes[0] {
-            byte if byte == Type::Integer as u8 => {
+            byte if byte == Tag::Integer as u8 => {
                 // tag (1 byte), number (8 bytes)
                 let number = byte_reader::read_u64(&bytes[1..])
                     .ok_or("couldn't read valid integer".to_owned())?;
 
-                Ok((9, Value::Integer(number)))
+                Ok((9, Value::Integer(number as i64)))
             }
-            byte if byte == Type::String as u8 => {
+            byte if byte == Tag::Float as u8 => {
+                // tag (1 byte), IEEE 754 bits (8 bytes)
+                let bits = byte_reader::read_u64(&bytes[1..])
+                    .ok_or("couldn't read valid float".to_owned())?;
+
+                Ok((9, Value::Float(f64::from_bits(bits))))
+            }
+            byte if byte == Tag::String as u8 => {
                 // tag (1 byte), length (8 bytes), data (variable)
                 let size = byte_reader::read_u64(&bytes[1..])
                     .ok_or("couldn't read string length".to_owned())?;
@@ -52,13 +265,184 @@ impl Value {
                 let value = Value::String(string.to_owned());
                 Ok((9 + size as usize, value))
             }
+            byte if byte == Tag::Boolean as u8 => {
+                // tag (1 byte), value (1 byte)
+                Ok((2, Value::Boolean(bytes[1] != 0)))
+            }
+            byte if byte == Tag::Nil as u8 => Ok((1, Value::Nil)),
+            byte if byte == Tag::Function as u8 => {
+                // tag (1 byte), arity (4 bytes), address (4 bytes), name length (8 bytes),
+                // name (variable)
+                let arity = byte_reader::read_u32(&bytes[1..])
+                    .ok_or("couldn't read function arity".to_owned())?;
+                let address = byte_reader::read_u32(&bytes[5..])
+                    .ok_or("couldn't read function address".to_owned())?;
+                let size = byte_reader::read_u64(&bytes[9..])
+                    .ok_or("couldn't read function name length".to_owned())?;
+                let name = std::str::from_utf8(&bytes[17..(17 + size as usize)])
+                    .map_err(|_| "failed to read function name")?;
+                let function = Function {
+                    name: name.to_owned(),
+                    arity,
+                    address,
+                };
+                Ok((17 + size as usize, Value::Function(function)))
+            }
+            byte if byte == Tag::Symbol as u8 => {
+                // tag (1 byte), length (8 bytes), name (variable)
+                let size = byte_reader::read_u64(&bytes[1..])
+                    .ok_or("couldn't read symbol length".to_owned())?;
+                let symbol = std::str::from_utf8(&bytes[9..(9 + size as usize)])
+                    .map_err(|_| "failed to read symbol")?;
+                Ok((9 + size as usize, Value::Symbol(symbol.to_owned())))
+            }
+            byte if byte == Tag::Array as u8 => {
+                // tag (1 byte), length (8 bytes), elements (variable)
+                let count = byte_reader::read_u64(&bytes[1..])
+                    .ok_or("couldn't read array length".to_owned())?;
+                let (size, elements) = Value::values_from_bytes(&bytes[9..], count as usize)?;
+                Ok((9 + size, Value::Array(elements)))
+            }
+            byte if byte == Tag::Hashmap as u8 => {
+                // tag (1 byte), pair count (8 bytes), alternating keys and values (variable)
+                let count = byte_reader::read_u64(&bytes[1..])
+                    .ok_or("couldn't read hashmap length".to_owned())?;
+                let (size, elements) = Value::values_from_bytes(&bytes[9..], 2 * count as usize)?;
+                Ok((9 + size, Value::hashmap(elements)))
+            }
+            byte if byte == Tag::Type as u8 => {
+                // tag (1 byte), constructable (1 byte), name (variable), field count (8 bytes),
+                // field names (variable), supertype or nil (variable)
+                let constructable = bytes[1] != 0;
+                let mut read = 2;
+                let name = read_str(&bytes[read..], &mut read)?;
+                let count = byte_reader::read_u64(&bytes[read..])
+                    .ok_or("couldn't read type field count".to_owned())?;
+                read += 8;
+                let mut fields = vec![];
+                for _ in 0..count {
+                    fields.push(read_str(&bytes[read..], &mut read)?);
+                }
+                let (size, supertype) = Value::from_bytes(&bytes[read..])?;
+                let supertype = match supertype {
+                    Value::Type(supertype) => Some(Box::new(supertype)),
+                    Value::Nil => None,
+                    _ => return Err("invalid supertype".to_owned()),
+                };
+                let type_ = Type {
+                    name,
+                    supertype,
+                    fields,
+                    constructable,
+                };
+                Ok((read + size, Value::Type(type_)))
+            }
+            byte if byte == Tag::Object as u8 => {
+                // tag (1 byte), type (variable), field count (8 bytes), field names and values
+                // (variable)
+                let (mut read, type_) = match Value::from_bytes(&bytes[1..])? {
+                    (size, Value::Type(type_)) => (1 + size, type_),
+                    _ => return Err("invalid object type".to_owned()),
+                };
+                let count = byte_reader::read_u64(&bytes[read..])
+                    .ok_or("couldn't read object field count".to_owned())?;
+                read += 8;
+                let mut fields = vec![];
+                for _ in 0..count {
+                    let name = read_str(&bytes[read..], &mut read)?;
+                    let (size, value) = Value::from_bytes(&bytes[read..])?;
+                    read += size;
+                    fields.push((name, value));
+                }
+                Ok((read, Value::Object(Object { type_, fields })))
+            }
             _ => Err("Unknown literal type".to_owned()),
         }
     }
+
+    fn values_from_bytes(bytes: &[u8], count: usize) -> Result<(usize, Vec<Value>), String> {
+        let mut read = 0;
+        let mut values = vec![];
+        for _ in 0..count {
+            let (size, value) = Value::from_bytes(&bytes[read..])?;
+            read += size;
+            values.push(value);
+        }
+        Ok((read, values))
+    }
+}
+
+// Writes a length (8 bytes) followed by the string's bytes
+fn write_str(bytes: &mut Vec<u8>, string: &str) {
+    bytes.extend((string.len() as u64).to_be_bytes());
+    bytes.extend(string.bytes());
+}
+
+// Reads a string written by `write_str`, adding the number of bytes read to `read`
+fn read_str(bytes: &[u8], read: &mut usize) -> Result<String, String> {
+    let size = byte_reader::read_u64(bytes).ok_or("couldn't read string length".to_owned())?;
+    let string =
+        std::str::from_utf8(&bytes[8..(8 + size as usize)]).map_err(|_| "failed to read string")?;
+    *read += 8 + size as usize;
+    Ok(string.to_owned())
 }
 
 #[repr(u8)]
-pub enum Type {
+pub enum Tag {
     Integer = 0,
     String,
+    Float,
+    Boolean,
+    Nil,
+    Function,
+    Symbol,
+    Array,
+    Hashmap,
+    Type,
+    Object,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_survive_serialisation() {
+        let user = Type {
+            name: "User".to_owned(),
+            supertype: Some(Box::new(Type::builtin("Base"))),
+            fields: vec!["name".to_owned()],
+            constructable: true,
+        };
+        let values = vec![
+            Value::Integer(-1),
+            Value::Symbol("foo".to_owned()),
+            Value::Array(vec![Value::Nil, Value::String("a".to_owned())]),
+            Value::hashmap(vec![Value::Symbol("a".to_owned()), Value::Float(1.5)]),
+            Value::Type(user.clone()),
+            Value::Object(Object {
+                type_: user,
+                fields: vec![("name".to_owned(), Value::Boolean(true))],
+            }),
+        ];
+        for value in values {
+            let bytes = value.to_bytes();
+            assert_eq!(Value::from_bytes(&bytes), Ok((bytes.len(), value)));
+        }
+    }
+
+    #[test]
+    fn integers_equal_floats_holding_the_same_number() {
+        assert_eq!(Value::Integer(1), Value::Float(1.0));
+        assert_eq!(Value::Float(-2.0), Value::Integer(-2));
+        assert_ne!(Value::Integer(1), Value::Float(1.5));
+        assert_ne!(Value::Integer(1), Value::String("1".to_owned()));
+        assert_ne!(
+            Value::Integer(1 << 53 | 1),
+            Value::Float((1u64 << 53) as f64)
+        );
+        assert_ne!(Value::Integer(i64::MAX), Value::Float(i64::MAX as f64));
+        assert_eq!(Value::Integer(i64::MIN), Value::Float(i64::MIN as f64));
+        assert_ne!(Value::Integer(0), Value::Float(f64::NAN));
+    }
 }
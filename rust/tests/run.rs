@@ -0,0 +1,117 @@
+use std::process::Command;
+
+// Runs the source as a script with the emerald binary, giving its exit code and what it wrote to
+// stdout and stderr, where the script's path is shown as `script.em`
+fn run(name: &str, source: &str) -> (i32, String, String) {
+    run_with(&[], name, source)
+}
+
+fn run_with(options: &[&str], name: &str, source: &str) -> (i32, String, String) {
+    let path = std::env::temp_dir().join(format!("emerald-{}-{}.em", name, std::process::id()));
+    std::fs::write(&path, source).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_emerald"))
+        .args(options)
+        .arg(&path)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    (
+        output.status.code().unwrap(),
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .replace(path.to_str().unwrap(), "script.em"),
+    )
+}
+
+// Nothing can be printed yet, so a script's output should be empty, with errors on stderr
+#[test]
+fn scripts_write_only_their_own_output() {
+    let (status, stdout, stderr) = run("calls", "defn inc x -> + x 1\ndef two (inc 1)\ninc two\n");
+    assert_eq!(status, 0);
+    assert_eq!(stdout, "");
+    assert_eq!(stderr, "");
+
+    let (status, stdout, stderr) = run("raise", "raise (NameError {:message \"oops\"})\n");
+    assert_eq!(status, 1);
+    assert_eq!(stdout, "");
+    assert_eq!(stderr, "script.em:1:1: NameError: oops\n");
+}
+
+#[test]
+fn scripts_exit_with_an_error_status_when_they_fail() {
+    assert_eq!(run("success", "+ 1 2\n").0, 0);
+
+    let (status, _, stderr) = run("uncaught", "  / 1 0\n");
+    assert_eq!(
+        (status, stderr.as_str()),
+        (1, "script.em:1:3: RuntimeError: divided by 0\n")
+    );
+
+    let (status, _, stderr) = run("compile", "+ 1\n");
+    assert_eq!(
+        (status, stderr.as_str()),
+        (
+            1,
+            "script.em:1:1: CompileError: Wrong number of arguments for `+` (given 1, expected 2)\n"
+        )
+    );
+
+    let (status, _, stderr) = run("syntax", "def x )\n");
+    assert_eq!(
+        (status, stderr.as_str()),
+        (1, "script.em:1:7: SyntaxError: Expected value, got `)`\n")
+    );
+
+    let (status, _, stderr) = run("raised", "raise (Error \"x\")\n");
+    assert_eq!((status, stderr.as_str()), (1, "script.em:1:1: Error: x\n"));
+
+    let (status, _, _) = run(
+        "rescued",
+        "try do\n  raise (Error \"x\")\nrescue Error (fn e -> 1)\nend\n",
+    );
+    assert_eq!(status, 0);
+}
+
+#[test]
+fn scripts_can_be_run_without_comment_handling() {
+    let source = "#!/usr/bin/env emerald\n+ 1 2\n";
+    assert_eq!(run_with(&["--no-comments"], "shebang", source).0, 0);
+
+    let source = "#!/usr/bin/env emerald\n# one\n1\n";
+    assert_eq!(run("comment", source).0, 0);
+    let (status, _, stderr) = run_with(&["--no-comments"], "comment", source);
+    assert_eq!(
+        (status, stderr.as_str()),
+        (1, "script.em:2:1: SyntaxError: Unexpected input `#`\n")
+    );
+}
+
+// Modules and imports can be parsed, but not run yet
+#[test]
+fn scripts_using_modules_or_imports_are_rejected() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../emerald/lib/test.em");
+    let output = Command::new(env!("CARGO_BIN_EXE_emerald"))
+        .arg(path)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        format!(
+            "{}:1:1: CompileError: Modules are not supported yet, so `defmodule Test` can't be \
+             compiled\n",
+            path
+        )
+    );
+
+    let (status, _, stderr) = run("import", "import \"test\"\n1\n");
+    assert_eq!(
+        (status, stderr.as_str()),
+        (
+            1,
+            "script.em:1:8: CompileError: Imports are not supported yet, so `import \"test\"` \
+             can't be compiled\n"
+        )
+    );
+}